@@ -14,9 +14,13 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see https://www.gnu.org/licenses/.
 
-use crate::config::expand_home;
+use crate::{
+    config::expand_home, eventlog::LogFormat, images::ImageFormat, input::is_pattern,
+    timestamps::TimestampFormat, AreaBound, Backend, Codec, Container, Span,
+};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use clap::ArgAction::{Set, SetTrue};
-pub use clap::Parser;
+pub use clap::{Parser, Subcommand};
 use std::{fs, path::PathBuf};
 
 /// Custom parser for `directory` field.
@@ -30,15 +34,50 @@ pub fn parse_directory(directory: &str) -> Result<PathBuf, String> {
     Ok(path)
 }
 
-/// Custom parser for `video` field.
+/// Custom parser for `video` field: a video file, a directory or a glob pattern.
 fn parse_video(video: &str) -> Result<PathBuf, String> {
-    let video = expand_home(&PathBuf::from(video));
-    match video.is_file() {
-        true => Ok(video),
+    let path = expand_home(&PathBuf::from(video));
+    match path.is_file() || path.is_dir() || is_pattern(video) {
+        true => Ok(path),
+        false => Err(String::from(
+            "the given path is not a file, a directory or a glob pattern",
+        )),
+    }
+}
+
+/// Custom parser for `images` field: a directory or a printf-style pattern.
+fn parse_images(images: &str) -> Result<PathBuf, String> {
+    let path = expand_home(&PathBuf::from(images));
+    match path.is_dir() || images.contains('%') {
+        true => Ok(path),
+        false => Err(String::from(
+            "the given path is not a directory or a printf-style pattern",
+        )),
+    }
+}
+
+/// Custom parser for file fields (`mask`).
+fn parse_file(file: &str) -> Result<PathBuf, String> {
+    let file = expand_home(&PathBuf::from(file));
+    match file.is_file() {
+        true => Ok(file),
         false => Err(String::from("the given path is not a file")),
     }
 }
 
+/// Custom parser for `video_start` field: RFC 3339 or local `%Y-%m-%dT%H:%M:%S` date&time.
+fn parse_datetime(datetime: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(datetime) {
+        return Ok(datetime.with_timezone(&Local));
+    }
+
+    NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+        .and_then(|datetime| Local.from_local_datetime(&datetime).earliest())
+        .ok_or_else(|| String::from("invalid date&time (expected i.e. 2022-06-23T11:49:00)"))
+}
+
 /// OpenCV motion detection/video-recording tool developed for research on Bumblebees.
 #[derive(Parser, Debug)]
 #[clap(
@@ -52,15 +91,88 @@ pub struct Args {
     #[clap(short, long, action = Set)]
     pub index: Option<u8>,
 
-    /// Video file as input.
+    /// Consecutive frame grabbing failures before reconnecting the camera (0 disables
+    /// reconnection).
+    #[clap(long, action = Set)]
+    pub reconnect_after: Option<u32>,
+
+    /// Delay (seconds) before the first camera reconnection attempt, doubled after each failed
+    /// attempt (up to 60 seconds).
+    #[clap(long, action = Set)]
+    pub reconnect_backoff: Option<f64>,
+
+    /// GStreamer pipeline as input, ending with `appsink` (requires OpenCV built with
+    /// GStreamer).
+    #[clap(
+        long,
+        action = Set,
+        conflicts_with_all = &["index", "height", "width", "video", "url", "images"]
+    )]
+    pub input_pipeline: Option<String>,
+
+    /// GStreamer pipeline as video output sink, starting with `appsrc` (`{path}` is replaced by
+    /// the output video file path; requires OpenCV built with GStreamer).
+    #[clap(long, action = Set)]
+    pub output_pipeline: Option<String>,
+
+    /// Network stream URL as input (RTSP, HTTP, MJPEG over HTTP).
+    #[clap(
+        short,
+        long,
+        action = Set,
+        conflicts_with_all = &["index", "height", "width", "video", "images"]
+    )]
+    pub url: Option<String>,
+
+    /// Network stream open & read timeout (seconds).
+    #[clap(long, action = Set)]
+    pub stream_timeout: Option<f64>,
+
+    /// Video file as input: a directory or a glob pattern (quoted) processes each video file in
+    /// turn, naming outputs after the input files.
     #[clap(
         short,
         long,
         value_parser = parse_video,
-        conflicts_with_all = &["index", "overlay", "height", "width", "framerate"]
+        conflicts_with_all = &[
+            "index",
+            "height",
+            "width",
+            "framerate",
+            "reconnect-after",
+            "reconnect-backoff"
+        ]
     )]
     pub video: Option<PathBuf>,
 
+    /// Image sequence as input: a directory (its image files, in natural order) or a printf-style
+    /// pattern (i.e. `IMG_%04d.JPG`), played at `framerate` and timestamped with the images
+    /// EXIF date&time (or file modification time).
+    #[clap(
+        long,
+        value_parser = parse_images,
+        conflicts_with_all = &[
+            "index",
+            "height",
+            "width",
+            "video",
+            "reconnect-after",
+            "reconnect-backoff"
+        ]
+    )]
+    pub images: Option<PathBuf>,
+
+    /// Video file recording start date&time (i.e. 2022-06-23T11:49:00), used to timestamp
+    /// frames (default: parsed from the video filename using `format`, or derived from the
+    /// video file modification time), not allowed for batch input.
+    #[clap(long, value_parser = parse_datetime, requires = "video")]
+    pub video_start: Option<DateTime<Local>>,
+
+    /// Number of video files processed in parallel with directory or glob pattern video input
+    /// (0 for all CPU cores).
+    #[clap(short, long, action = Set, requires = "video")]
+    pub jobs: Option<usize>,
+
     /// Video capture frame height.
     #[clap(short = 'H', long, action = Set)]
     pub height: Option<u16>,
@@ -82,10 +194,135 @@ pub struct Args {
     #[clap(long, action = Set)]
     pub format: Option<String>,
 
+    /// Output video codec [possible values: mjpg, xvid, mp4v, h264].
+    #[clap(long, value_parser)]
+    pub codec: Option<Codec>,
+
+    /// Output video container [possible values: mkv, avi, mp4].
+    #[clap(long, value_parser)]
+    pub container: Option<Container>,
+
+    /// Save motion frames as image files named after their date&time, instead of writing video
+    /// files [possible values: jpg, png].
+    #[clap(long, value_parser)]
+    pub image_output: Option<ImageFormat>,
+
+    /// Save an image of each event frame with the largest motion area next to the output
+    /// (named after the event start date&time and event counter).
+    #[clap(long, action = SetTrue)]
+    pub snapshots: bool,
+
+    /// Draw the motion contours bounding rectangles on event snapshots.
+    #[clap(long, action = SetTrue)]
+    pub snapshot_boxes: bool,
+
+    /// Save a contact sheet tiling the session event snapshots with their date&time.
+    #[clap(long, action = SetTrue)]
+    pub contact_sheet: bool,
+
+    /// One output video file per motion event (named after the event start date&time and
+    /// event counter).
+    #[clap(short, long, action = SetTrue)]
+    pub clips: bool,
+
+    /// Write a per-event log file next to the output videos [possible values: jsonl, csv].
+    #[clap(short, long, value_parser)]
+    pub event_log: Option<LogFormat>,
+
+    /// Write a frame timestamp sidecar file next to each output video, mapping every frame to
+    /// its capture date&time [possible values: srt, vtt, csv].
+    #[clap(short, long, value_parser)]
+    pub timestamps: Option<TimestampFormat>,
+
     /// Date&Time video overlay.
     #[clap(short, long, action = SetTrue)]
     pub overlay: bool,
 
+    /// Motion debug video overlay: contour bounding rectangles (accepted in green, rejected in
+    /// red), motion area percentage and frame index.
+    #[clap(long, action = SetTrue)]
+    pub debug_overlay: bool,
+
+    /// Write a debug video (frame & motion mask side by side) next to each output video.
+    #[clap(long, action = SetTrue)]
+    pub debug_video: bool,
+
+    /// Track video overlay: bounding rectangles & IDs of the tracked motion contours.
+    #[clap(long, action = SetTrue)]
+    pub track_overlay: bool,
+
+    /// Frames (integer) or seconds (with `s` suffix, i.e. `1.5s`) preceding motion to be
+    /// written before motion frames.
+    #[clap(long, value_parser)]
+    pub pre_roll: Option<Span>,
+
+    /// Frames (integer) or seconds (with `s` suffix, i.e. `2s`) without motion to be written
+    /// after the last motion frame.
+    #[clap(long, value_parser)]
+    pub post_roll: Option<Span>,
+
+    /// Minimum consecutive motion frames to start an event.
+    #[clap(long, action = Set)]
+    pub min_motion_frames: Option<u32>,
+
+    /// Motion detection working frame width.
+    #[clap(long, action = Set)]
+    pub detect_width: Option<u16>,
+
+    /// Motion detection working frame height.
+    #[clap(long, action = Set)]
+    pub detect_height: Option<u16>,
+
+    /// Motion detection Gaussian blur kernel size (must be odd).
+    #[clap(long, action = Set)]
+    pub kernel_size: Option<u8>,
+
+    /// Motion detection Gaussian kernel standard deviation.
+    #[clap(long, action = Set)]
+    pub sigma: Option<f64>,
+
+    /// Motion detection binary threshold (0-255).
+    #[clap(long, action = Set)]
+    pub threshold: Option<u8>,
+
+    /// Motion detection dilation iterations.
+    #[clap(long, action = Set)]
+    pub dilate_iterations: Option<u8>,
+
+    /// Minimum contour area considered motion, in pixels (integer) or as a fraction of the frame
+    /// area (float).
+    #[clap(long, value_parser)]
+    pub min_area: Option<AreaBound>,
+
+    /// Maximum contour area considered motion, in pixels (integer) or as a fraction of the frame
+    /// area (float).
+    #[clap(long, value_parser)]
+    pub max_area: Option<AreaBound>,
+
+    /// Motion detection mask image (white means watch).
+    #[clap(long, value_parser = parse_file)]
+    pub mask: Option<PathBuf>,
+
+    /// Motion detection backend [possible values: frame-diff, running-average, mog2, knn].
+    #[clap(long, value_parser)]
+    pub backend: Option<Backend>,
+
+    /// Background model learning rate (running-average, mog2, knn backends).
+    #[clap(long, action = Set)]
+    pub learning_rate: Option<f64>,
+
+    /// Background subtractor history length (mog2, knn backends).
+    #[clap(long, action = Set)]
+    pub history: Option<u32>,
+
+    /// Background subtractor variance (mog2) or squared distance (knn) threshold.
+    #[clap(long, action = Set)]
+    pub var_threshold: Option<f64>,
+
+    /// Detect shadows and exclude them from motion (mog2, knn backends).
+    #[clap(long, action = SetTrue)]
+    pub detect_shadows: bool,
+
     /// Disable colored output.
     #[clap(long, action = SetTrue)]
     pub no_color: bool,
@@ -93,4 +330,26 @@ pub struct Args {
     /// Mute standard output.
     #[clap(short, long, action = SetTrue)]
     pub quiet: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the available capture cameras and the resolution/framerate combinations they deliver.
+    Probe(ProbeArgs),
+}
+
+/// `probe` subcommand arguments.
+#[derive(clap::Args, Debug)]
+pub struct ProbeArgs {
+    /// /dev/video<INDEX> capture camera index (all cameras if not given).
+    #[clap(short, long, action = Set)]
+    pub index: Option<u8>,
+
+    /// Print JSON rather than a table.
+    #[clap(long, action = SetTrue)]
+    pub json: bool,
 }
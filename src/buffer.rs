@@ -0,0 +1,66 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use std::collections::{vec_deque::Drain, VecDeque};
+
+/// Pre-roll ring buffer: holds the latest frames preceding motion, so that they can be written
+/// before the frames in which motion has been detected.
+///
+/// # Fields
+/// * items: buffered items, oldest first
+/// * capacity: maximum number of buffered items (0 disables buffering)
+#[derive(Debug)]
+pub struct PreRoll<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> PreRoll<T> {
+    /// Create an empty pre-roll buffer holding at most `capacity` items.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Push `item` into the buffer, dropping the oldest item if the buffer is full.
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    /// Empty the buffer, returning the buffered items in order (oldest first).
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        self.items.drain(..)
+    }
+
+    /// Return the number of buffered items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Return whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
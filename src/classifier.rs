@@ -0,0 +1,258 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+//! Second motion detection stage: contour crops classification through OpenCV's `dnn` module
+//! (CPU backend), rejecting non-bee motion (ants, flies, wasps, leaves, shadows, ...).
+
+use crate::{config::expand_home, error::ErrorKind, Blob};
+use opencv::{
+    core::{Rect, Scalar, Size, CV_32F, CV_8UC3},
+    dnn::{blob_from_image, read_net, Net, DNN_BACKEND_OPENCV, DNN_TARGET_CPU},
+    prelude::{Mat, MatTraitConst, NetTrait, NetTraitConst},
+};
+use serde::Deserialize;
+use std::{fmt, fs, path::PathBuf};
+
+/// Default value for the minimum top class confidence to accept a contour.
+fn default_confidence() -> f32 {
+    0.5
+}
+
+/// Default value for the network input width & height.
+fn default_input_size() -> u16 {
+    224
+}
+
+/// Default value for the network input pixel values scale factor.
+fn default_scale() -> f64 {
+    1. / 255.
+}
+
+/// Default value for swapping the red & blue channels (network trained on RGB images).
+fn default_swap_rb() -> bool {
+    true
+}
+
+/// Contour classification parameters.
+///
+/// # Fields
+/// * model: trained model file (`.onnx`, or `.caffemodel` along with `config`)
+/// * config: network configuration file (`.prototxt`), Caffe models only
+/// * labels: class labels file, one label per line in model output order
+/// * allow: labels of the classes accepted as motion
+/// * confidence: minimum top class confidence to accept a contour (0-1)
+/// * width: network input width
+/// * height: network input height
+/// * scale: network input pixel values scale factor
+/// * mean: mean BGR values subtracted from the network input (before scaling)
+/// * swap_rb: swap the red & blue channels (network trained on RGB images)
+/// * softmax: apply softmax to the model output (model returning raw scores)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassifierParams {
+    pub model: PathBuf,
+    #[serde(default)]
+    pub config: Option<PathBuf>,
+    pub labels: PathBuf,
+    pub allow: Vec<String>,
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+    #[serde(default = "default_input_size")]
+    pub width: u16,
+    #[serde(default = "default_input_size")]
+    pub height: u16,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub mean: [f64; 3],
+    #[serde(default = "default_swap_rb")]
+    pub swap_rb: bool,
+    #[serde(default)]
+    pub softmax: bool,
+}
+
+impl ClassifierParams {
+    /// Check contour classification parameters consistency.
+    pub fn validate(&self) -> Result<(), ErrorKind> {
+        if self.allow.is_empty() {
+            return Err(ErrorKind::InvalidClassifierParams(String::from(
+                "allow list must not be empty",
+            )));
+        }
+
+        if !(0. ..=1.).contains(&self.confidence) {
+            return Err(ErrorKind::InvalidClassifierParams(format!(
+                "confidence must be in [0, 1], found {}",
+                self.confidence
+            )));
+        }
+
+        if self.width == 0 || self.height == 0 {
+            return Err(ErrorKind::InvalidClassifierParams(format!(
+                "input size must be positive, found {}x{}",
+                self.width, self.height
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Contour classifier: runs the model on the crop of each motion contour bounding rectangle,
+/// accepting the contours whose top class is allowed with enough confidence.
+///
+/// # Fields
+/// * params: contour classification parameters
+/// * net: OpenCV deep neural network
+/// * allowed: allowed classes, by model output index
+pub struct Classifier {
+    params: ClassifierParams,
+    net: Net,
+    allowed: Vec<bool>,
+}
+
+impl fmt::Debug for Classifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Classifier")
+            .field("params", &self.params)
+            .field("allowed", &self.allowed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Classifier {
+    /// Create an instance of the Classifier, loading the labels & the model: return Err if
+    /// `params` are not valid, labels or model can't be loaded, or the model doesn't accept
+    /// `width` x `height` input.
+    pub fn new(params: ClassifierParams) -> Result<Self, ErrorKind> {
+        params.validate()?;
+
+        let labels = fs::read_to_string(expand_home(&params.labels))
+            .map_err(|_| ErrorKind::InvalidLabels)?;
+        let labels: Vec<&str> = labels.lines().map(str::trim).collect();
+        if let Some(label) = params.allow.iter().find(|a| !labels.contains(&a.as_str())) {
+            return Err(ErrorKind::InvalidClassifierParams(format!(
+                "allowed label '{label}' not found in labels file"
+            )));
+        }
+        let allowed = labels
+            .iter()
+            .map(|label| params.allow.iter().any(|a| a == label))
+            .collect();
+
+        let model = expand_home(&params.model);
+        let config = params
+            .config
+            .as_deref()
+            .map(expand_home)
+            .unwrap_or_default();
+        let mut net = match read_net(
+            model.to_str().expect("invalid UTF-8 model path"),
+            config.to_str().expect("invalid UTF-8 model config path"),
+            "", // Framework inferred from the model file extension.
+        ) {
+            Ok(net) if !net.empty().unwrap_or(true) => net,
+            _ => return Err(ErrorKind::InvalidModel),
+        };
+        net.set_preferable_backend(DNN_BACKEND_OPENCV)
+            .and_then(|_| net.set_preferable_target(DNN_TARGET_CPU))
+            .map_err(|_| ErrorKind::InvalidModel)?;
+
+        let mut classifier = Self {
+            params,
+            net,
+            allowed,
+        };
+
+        // Dummy forward pass: input shape mismatches surface here rather than on the first
+        // motion frame.
+        let (width, height) = (classifier.params.width, classifier.params.height);
+        Mat::new_rows_cols_with_default(height.into(), width.into(), CV_8UC3, Scalar::all(0.))
+            .and_then(|dummy| classifier.top_class(&dummy))
+            .map_err(|_| {
+                ErrorKind::InvalidClassifierParams(format!(
+                    "model doesn't accept {width}x{height} input"
+                ))
+            })?;
+
+        Ok(classifier)
+    }
+
+    /// Return the index & confidence of the top class for the `crop` image.
+    fn top_class(&mut self, crop: &Mat) -> opencv::Result<(usize, f32)> {
+        let [b, g, r] = self.params.mean;
+        let input = blob_from_image(
+            crop,
+            self.params.scale,
+            Size::new(self.params.width.into(), self.params.height.into()),
+            Scalar::new(b, g, r, 0.),
+            self.params.swap_rb,
+            false, // Resize the whole crop, don't center crop it.
+            CV_32F,
+        )?;
+        self.net.set_input(&input, "", 1., Scalar::default())?;
+        let output = self.net.forward_single("")?;
+        let scores = output.data_typed::<f32>()?;
+
+        let (index, score) =
+            scores
+                .iter()
+                .enumerate()
+                .fold((0, f32::NEG_INFINITY), |top, (i, &score)| {
+                    match score > top.1 {
+                        true => (i, score),
+                        false => top,
+                    }
+                });
+        let confidence = match self.params.softmax {
+            // Top class probability: 1 / sum(exp(score - top score)).
+            true => 1. / scores.iter().map(|s| (s - score).exp()).sum::<f32>(),
+            false => score,
+        };
+
+        Ok((index, confidence))
+    }
+
+    /// Split `blobs` in `frame` into accepted (top class allowed with enough confidence) and
+    /// rejected contours.
+    pub fn classify(
+        &mut self,
+        frame: &Mat,
+        blobs: Vec<Blob>,
+    ) -> Result<(Vec<Blob>, Vec<Blob>), ErrorKind> {
+        let bounds = Rect::new(0, 0, frame.cols(), frame.rows());
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+
+        for blob in blobs {
+            let rect = blob.rect & bounds;
+            let allowed = rect.area() > 0 && {
+                let (index, confidence) = Mat::roi(frame, rect)
+                    .and_then(|crop| self.top_class(&crop))
+                    .map_err(|_| ErrorKind::ClassifierErr)?;
+                // Model outputs beyond the labels are never allowed.
+                self.allowed.get(index).copied().unwrap_or(false)
+                    && confidence >= self.params.confidence
+            };
+
+            match allowed {
+                true => accepted.push(blob),
+                false => rejected.push(blob),
+            }
+        }
+
+        Ok((accepted, rejected))
+    }
+}
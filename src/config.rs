@@ -14,7 +14,13 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see https://www.gnu.org/licenses/.
 
-use crate::{args::Args, error::ErrorKind};
+use crate::{
+    args::Args, classifier::ClassifierParams, error::ErrorKind, eventlog::LogFormat,
+    images::ImageFormat, input, markers::MarkerParams, tags::ColorTag, timestamps::TimestampFormat,
+    tracker::TrackerParams, CameraControls, Codec, Container, DetectorParams, Span,
+    DEFAULT_RECONNECT_AFTER, DEFAULT_RECONNECT_BACKOFF,
+};
+use chrono::{DateTime, Local};
 use directories::BaseDirs;
 use serde::{de, Deserialize, Deserializer};
 use std::{
@@ -87,22 +93,86 @@ fn default_format() -> String {
     String::from("%Y-%m-%dT%H:%M:%S")
 }
 
+/// Default value for minimum consecutive motion frames to start an event.
+fn default_min_motion_frames() -> u32 {
+    1
+}
+
+/// Default value for consecutive frame grabbing failures before reconnecting the camera.
+fn default_reconnect_after() -> u32 {
+    DEFAULT_RECONNECT_AFTER
+}
+
+/// Default value for delay (seconds) before the first camera reconnection attempt.
+fn default_reconnect_backoff() -> f64 {
+    DEFAULT_RECONNECT_BACKOFF
+}
+
+/// Default value for network stream open & read timeout (seconds).
+fn default_stream_timeout() -> f64 {
+    10.
+}
+
+/// Default value for number of video files processed in parallel.
+fn default_jobs() -> usize {
+    1
+}
+
 /// Default value for Date&Time overlay border.
 fn default_overlay_border() -> u8 {
     3
 }
 
 /// Configuration options.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     /// /dev/video<index> capture camera index.
     #[serde(default = "default_index")]
     pub index: u8,
 
+    /// Consecutive frame grabbing failures before reconnecting the camera (0 disables
+    /// reconnection).
+    #[serde(default = "default_reconnect_after")]
+    pub reconnect_after: u32,
+
+    /// Delay (seconds) before the first camera reconnection attempt, doubled after each failed
+    /// attempt.
+    #[serde(default = "default_reconnect_backoff")]
+    pub reconnect_backoff: f64,
+
+    /// GStreamer pipeline as input (ending with `appsink`).
+    #[serde(default)]
+    pub input_pipeline: Option<String>,
+
+    /// GStreamer pipeline as video output sink (starting with `appsrc`, `{path}` is replaced by
+    /// the output video file path).
+    #[serde(default)]
+    pub output_pipeline: Option<String>,
+
+    /// Network stream URL as input (RTSP, HTTP, MJPEG over HTTP).
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Network stream open & read timeout (seconds).
+    #[serde(default = "default_stream_timeout")]
+    pub stream_timeout: f64,
+
     /// Video file as input.
     #[serde(skip_deserializing)]
     pub video: Option<PathBuf>,
 
+    /// Image sequence as input (directory or printf-style pattern).
+    #[serde(skip_deserializing)]
+    pub images: Option<PathBuf>,
+
+    /// Video file recording start date&time.
+    #[serde(skip_deserializing)]
+    pub video_start: Option<DateTime<Local>>,
+
+    /// Number of video files processed in parallel (0 for all CPU cores).
+    #[serde(default = "default_jobs")]
+    pub jobs: usize,
+
     /// Video capture frame height.
     #[serde(default = "default_height")]
     pub height: u16,
@@ -127,6 +197,42 @@ pub struct Config {
     #[serde(default = "default_format")]
     pub format: String,
 
+    /// Output video codec.
+    #[serde(default)]
+    pub codec: Codec,
+
+    /// Output video container.
+    #[serde(default)]
+    pub container: Container,
+
+    /// Image sequence output format (motion frames saved as image files instead of video files).
+    #[serde(default)]
+    pub image_output: Option<ImageFormat>,
+
+    /// Save an image of each event frame with the largest motion area next to the output.
+    #[serde(default)]
+    pub snapshots: bool,
+
+    /// Draw the motion contours bounding rectangles on event snapshots.
+    #[serde(default)]
+    pub snapshot_boxes: bool,
+
+    /// Save a contact sheet tiling the session event snapshots.
+    #[serde(default)]
+    pub contact_sheet: bool,
+
+    /// One output video file per motion event.
+    #[serde(default)]
+    pub clips: bool,
+
+    /// Per-event log file format (no event log if not set).
+    #[serde(default)]
+    pub event_log: Option<LogFormat>,
+
+    /// Frame timestamp sidecar file format (no timestamp sidecar if not set).
+    #[serde(default)]
+    pub timestamps: Option<TimestampFormat>,
+
     /// Date&Time video overlay.
     #[serde(default)]
     pub overlay: bool,
@@ -135,6 +241,18 @@ pub struct Config {
     #[serde(default = "default_overlay_border")]
     pub overlay_border: u8,
 
+    /// Motion debug video overlay (contour bounding rectangles, motion area & frame index).
+    #[serde(default)]
+    pub debug_overlay: bool,
+
+    /// Debug video (frame & motion mask side by side) next to each output video.
+    #[serde(default)]
+    pub debug_video: bool,
+
+    /// Track video overlay (track bounding rectangles & IDs).
+    #[serde(default)]
+    pub track_overlay: bool,
+
     /// Disable colored output.
     #[serde(skip_deserializing, default)]
     pub no_color: bool,
@@ -142,6 +260,42 @@ pub struct Config {
     /// Mute standard output.
     #[serde(default)]
     pub quiet: bool,
+
+    /// Frames (or seconds) preceding motion to be written before motion frames.
+    #[serde(default)]
+    pub pre_roll: Span,
+
+    /// Frames (or seconds) without motion to be written after the last motion frame.
+    #[serde(default)]
+    pub post_roll: Span,
+
+    /// Minimum consecutive motion frames to start an event.
+    #[serde(default = "default_min_motion_frames")]
+    pub min_motion_frames: u32,
+
+    /// Camera controls.
+    #[serde(default)]
+    pub camera: CameraControls,
+
+    /// Motion detection parameters.
+    #[serde(default)]
+    pub detector: DetectorParams,
+
+    /// Tracking & boundary crossing counting parameters.
+    #[serde(default)]
+    pub tracker: TrackerParams,
+
+    /// Colour tags identifying marked individuals.
+    #[serde(default)]
+    pub tags: Vec<ColorTag>,
+
+    /// Fiducial marker decoding parameters (no decoding if not set).
+    #[serde(default)]
+    pub markers: Option<MarkerParams>,
+
+    /// Contour classification parameters (no classification if not set).
+    #[serde(default)]
+    pub classifier: Option<ClassifierParams>,
 }
 
 /// Implement the Default trait for Config.
@@ -150,16 +304,46 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             index: default_index(),
+            reconnect_after: default_reconnect_after(),
+            reconnect_backoff: default_reconnect_backoff(),
+            input_pipeline: None,
+            output_pipeline: None,
+            url: None,
+            stream_timeout: default_stream_timeout(),
             video: None,
+            images: None,
+            video_start: None,
+            jobs: default_jobs(),
             height: default_height(),
             width: default_width(),
             framerate: default_framerate(),
             directory: default_directory(),
             format: default_format(),
+            codec: Codec::default(),
+            container: Container::default(),
+            image_output: None,
+            snapshots: false,
+            snapshot_boxes: false,
+            contact_sheet: false,
+            clips: false,
+            event_log: None,
+            timestamps: None,
             overlay: false,
             overlay_border: default_overlay_border(),
+            debug_overlay: false,
+            debug_video: false,
+            track_overlay: false,
             no_color: false,
             quiet: false,
+            pre_roll: Span::default(),
+            post_roll: Span::default(),
+            min_motion_frames: default_min_motion_frames(),
+            camera: CameraControls::default(),
+            detector: DetectorParams::default(),
+            tracker: TrackerParams::default(),
+            tags: Vec::new(),
+            markers: None,
+            classifier: None,
         }
     }
 }
@@ -191,6 +375,17 @@ impl Config {
 
     /// Override configuration with command line arguments.
     pub fn override_with_args(mut self, args: Args) -> Self {
+        // Input given on the command line replaces the configured one.
+        let cli_input = args.index.is_some()
+            || args.video.is_some()
+            || args.images.is_some()
+            || args.url.is_some()
+            || args.input_pipeline.is_some();
+        if cli_input {
+            self.url = None;
+            self.input_pipeline = None;
+        }
+
         if let Some(directory) = args.directory {
             self.directory = directory;
         }
@@ -199,6 +394,42 @@ impl Config {
             self.format = format;
         }
 
+        if let Some(codec) = args.codec {
+            self.codec = codec;
+        }
+
+        if let Some(container) = args.container {
+            self.container = container;
+        }
+
+        if let Some(image_output) = args.image_output {
+            self.image_output = Some(image_output);
+        }
+
+        if args.snapshots {
+            self.snapshots = true;
+        }
+
+        if args.snapshot_boxes {
+            self.snapshot_boxes = true;
+        }
+
+        if args.contact_sheet {
+            self.contact_sheet = true;
+        }
+
+        if args.clips {
+            self.clips = true;
+        }
+
+        if let Some(event_log) = args.event_log {
+            self.event_log = Some(event_log);
+        }
+
+        if let Some(timestamps) = args.timestamps {
+            self.timestamps = Some(timestamps);
+        }
+
         if args.no_color {
             self.no_color = true;
         }
@@ -207,18 +438,50 @@ impl Config {
             self.quiet = true;
         }
 
-        // Input is video: disable overlay.
         if let Some(video) = args.video {
             self.video = Some(video);
-            if self.overlay {
-                self.overlay = false;
-            }
+        }
+
+        if let Some(input_pipeline) = args.input_pipeline {
+            self.input_pipeline = Some(input_pipeline);
+        }
+
+        if let Some(output_pipeline) = args.output_pipeline {
+            self.output_pipeline = Some(output_pipeline);
+        }
+
+        if let Some(url) = args.url {
+            self.url = Some(url);
+        }
+
+        if let Some(stream_timeout) = args.stream_timeout {
+            self.stream_timeout = stream_timeout;
+        }
+
+        if let Some(images) = args.images {
+            self.images = Some(images);
+        }
+
+        if let Some(video_start) = args.video_start {
+            self.video_start = Some(video_start);
+        }
+
+        if let Some(jobs) = args.jobs {
+            self.jobs = jobs;
         }
 
         if let Some(index) = args.index {
             self.index = index;
         }
 
+        if let Some(reconnect_after) = args.reconnect_after {
+            self.reconnect_after = reconnect_after;
+        }
+
+        if let Some(reconnect_backoff) = args.reconnect_backoff {
+            self.reconnect_backoff = reconnect_backoff;
+        }
+
         if let Some(height) = args.height {
             self.height = height;
         }
@@ -235,6 +498,110 @@ impl Config {
             self.overlay = true;
         }
 
+        if args.debug_overlay {
+            self.debug_overlay = true;
+        }
+
+        if args.debug_video {
+            self.debug_video = true;
+        }
+
+        if args.track_overlay {
+            self.track_overlay = true;
+        }
+
+        if let Some(pre_roll) = args.pre_roll {
+            self.pre_roll = pre_roll;
+        }
+
+        if let Some(post_roll) = args.post_roll {
+            self.post_roll = post_roll;
+        }
+
+        if let Some(min_motion_frames) = args.min_motion_frames {
+            self.min_motion_frames = min_motion_frames;
+        }
+
+        if let Some(detect_width) = args.detect_width {
+            self.detector.width = detect_width;
+        }
+
+        if let Some(detect_height) = args.detect_height {
+            self.detector.height = detect_height;
+        }
+
+        if let Some(kernel_size) = args.kernel_size {
+            self.detector.kernel_size = kernel_size;
+        }
+
+        if let Some(sigma) = args.sigma {
+            self.detector.sigma = sigma;
+        }
+
+        if let Some(threshold) = args.threshold {
+            self.detector.threshold = threshold;
+        }
+
+        if let Some(dilate_iterations) = args.dilate_iterations {
+            self.detector.dilate_iterations = dilate_iterations;
+        }
+
+        if let Some(min_area) = args.min_area {
+            self.detector.min_area = Some(min_area);
+        }
+
+        if let Some(max_area) = args.max_area {
+            self.detector.max_area = Some(max_area);
+        }
+
+        if let Some(mask) = args.mask {
+            self.detector.mask = Some(mask);
+        }
+
+        if let Some(backend) = args.backend {
+            self.detector.backend = backend;
+        }
+
+        if let Some(learning_rate) = args.learning_rate {
+            self.detector.learning_rate = Some(learning_rate);
+        }
+
+        if let Some(history) = args.history {
+            self.detector.history = history;
+        }
+
+        if let Some(var_threshold) = args.var_threshold {
+            self.detector.var_threshold = Some(var_threshold);
+        }
+
+        if args.detect_shadows {
+            self.detector.detect_shadows = true;
+        }
+
         self
     }
+
+    /// Check input & output options consistency.
+    pub fn validate(&self) -> Result<(), ErrorKind> {
+        for (name, seconds) in [
+            ("reconnect_backoff", self.reconnect_backoff),
+            ("stream_timeout", self.stream_timeout),
+        ] {
+            if !seconds.is_finite() || seconds < 0. {
+                return Err(ErrorKind::InvalidInputParams(format!(
+                    "{name} must be finite and non-negative, found {seconds}"
+                )));
+            }
+        }
+
+        // Every clip or batch input file needs its own output file.
+        let batch = self.video.as_deref().map_or(false, input::is_batch);
+        if let Some(pipeline) = &self.output_pipeline {
+            if (self.clips || batch) && !pipeline.contains("{path}") {
+                return Err(ErrorKind::OutputPipelinePath);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see https://www.gnu.org/licenses/.
 
+use crate::{Codec, Container};
 use std::fmt::{self, Display, Formatter};
 
 /// BombusCV error kinds.
@@ -25,16 +26,96 @@ pub enum ErrorKind {
     BrokenConfig(String),
     /// Occurs when VideoCapture is unable to open camera.
     InvalidCameraIndex,
+    /// Occurs when the live input (camera or network stream) can't be reopened on reconnection.
+    ReconnectErr,
+    /// Occurs when input options are not valid.
+    InvalidInputParams(String),
+    /// Occurs when a GStreamer pipeline is given but OpenCV was built without GStreamer.
+    GStreamerUnavailable,
+    /// Occurs when VideoCapture is unable to open GStreamer pipeline.
+    InvalidPipeline,
+    /// Occurs when the output pipeline has no `{path}` while writing several output files.
+    OutputPipelinePath,
+    /// Occurs when VideoCapture is unable to open network stream.
+    InvalidStream,
     /// Occurs when VideoCapture is unable to open video file.
     InvalidVideoFile,
+    /// Occurs when the video input directory or glob pattern can't be read.
+    InvalidInput,
+    /// Occurs when the video input directory or glob pattern contains no video file.
+    NoVideoInput,
+    /// Occurs when the video recording start date&time is given for a batch input.
+    BatchVideoStart,
+    /// Occurs when an image of the image sequence input can't be read.
+    InvalidImage,
+    /// Occurs when the image sequence directory or pattern matches no image file.
+    NoImageInput,
     /// Occurs when VideoWriter is unable to open video output file.
     InvalidOutput,
+    /// Occurs when a frame can't be saved to an image file (image sequence output, event
+    /// snapshot or contact sheet).
+    ImageWriteErr(String),
+    /// Occurs when the event snapshot or the contact sheet image can't be composed.
+    SnapshotErr,
     /// Occurs when VideoCapture read fails.
     FrameDropped,
     /// Occurs when VideoCapture returns an empty frame.
     EmptyFrame,
     /// Occurs when VideoWriter fails to print text overlay on video frame.
     TextOverlayErr,
+    /// Occurs when the debug frame (frame & motion mask side by side) can't be composed.
+    DebugFrameErr,
+    /// Occurs when motion detection parameters are not valid.
+    InvalidDetectorParams(String),
+    /// Occurs when tracking & counting parameters are not valid.
+    InvalidTrackerParams(String),
+    /// Occurs when the per-hour boundary crossing counts can't be written.
+    CountsWriteErr,
+    /// Occurs when colour tags are not valid.
+    InvalidColorTag(String),
+    /// Occurs when OpenCV fails looking for colour tags.
+    ColorTagErr,
+    /// Occurs when marker decoding parameters are not valid.
+    InvalidMarkerParams(String),
+    /// Occurs when marker decoding is requested, but bombuscv was built without the `aruco`
+    /// feature.
+    ArucoUnavailable,
+    /// Occurs when OpenCV is unable to create the marker dictionary or detection parameters.
+    MarkerReaderErr,
+    /// Occurs when the marker decoding fails.
+    MarkerDecodingErr,
+    /// Occurs when the marker log file can't be created.
+    InvalidMarkerLog,
+    /// Occurs when the marker log can't be written.
+    MarkerLogWriteErr,
+    /// Occurs when contour classification parameters are not valid.
+    InvalidClassifierParams(String),
+    /// Occurs when the class labels file can't be read.
+    InvalidLabels,
+    /// Occurs when OpenCV is unable to load the classification model.
+    InvalidModel,
+    /// Occurs when the contour classification fails.
+    ClassifierErr,
+    /// Occurs when the trajectories file can't be created.
+    InvalidTrajectories,
+    /// Occurs when the trajectories can't be written.
+    TrajectoriesWriteErr,
+    /// Occurs when the detection mask image can't be loaded.
+    InvalidMask,
+    /// Occurs when OpenCV is unable to create the background subtractor.
+    BackgroundSubtractorErr,
+    /// Occurs when the event log file can't be created.
+    InvalidEventLog,
+    /// Occurs when writing to the event log file fails.
+    EventLogWriteErr,
+    /// Occurs when the frame timestamp sidecar file can't be created.
+    InvalidTimestamps,
+    /// Occurs when writing to the frame timestamp sidecar file fails.
+    TimestampsWriteErr,
+    /// Occurs when the video codec can't be stored in the video container.
+    IncompatibleCodec(Codec, Container),
+    /// Occurs when the OpenCV build can't encode any codec in the video container.
+    UnsupportedCodec(Container),
 }
 
 impl Display for ErrorKind {
@@ -44,11 +125,74 @@ impl Display for ErrorKind {
             Self::ConfigNotFound => Some("no valid config path found".to_string()),
             Self::BrokenConfig(msg) => Some(msg.to_string()),
             Self::InvalidCameraIndex => Some("unable to open camera by index".to_string()),
+            Self::ReconnectErr => Some("unable to reconnect video input".to_string()),
+            Self::InvalidInputParams(msg) => Some(format!("invalid input parameters: {msg}")),
+            Self::GStreamerUnavailable => {
+                Some("OpenCV was built without GStreamer support".to_string())
+            }
+            Self::InvalidPipeline => Some("unable to open GStreamer pipeline".to_string()),
+            Self::OutputPipelinePath => Some(
+                "output pipeline must contain `{path}` with clips or batch input (one output file \
+                 each)"
+                    .to_string(),
+            ),
+            Self::InvalidStream => Some("unable to open network stream".to_string()),
             Self::InvalidVideoFile => Some("unable to open video file".to_string()),
+            Self::InvalidInput => Some("unable to read video input".to_string()),
+            Self::NoVideoInput => Some("no video file found in video input".to_string()),
+            Self::BatchVideoStart => Some(
+                "video start date&time can't be set for batch input (one start for every file)"
+                    .to_string(),
+            ),
+            Self::InvalidImage => Some("unable to read image sequence file".to_string()),
+            Self::NoImageInput => Some("no image file found in image sequence input".to_string()),
             Self::InvalidOutput => Some("unable to open video output file".to_string()),
+            Self::ImageWriteErr(path) => Some(format!("unable to write image file '{path}'")),
+            Self::SnapshotErr => Some("unable to compose event snapshot".to_string()),
             Self::FrameDropped => None,
             Self::EmptyFrame => Some("empty video frame".to_string()),
             Self::TextOverlayErr => Some("unable to print text overlay".to_string()),
+            Self::DebugFrameErr => Some("unable to compose debug frame".to_string()),
+            Self::InvalidDetectorParams(msg) => {
+                Some(format!("invalid motion detection parameters: {msg}"))
+            }
+            Self::InvalidTrackerParams(msg) => Some(format!("invalid tracker parameters: {msg}")),
+            Self::CountsWriteErr => Some("unable to write boundary crossing counts".to_string()),
+            Self::InvalidColorTag(msg) => Some(format!("invalid colour tag: {msg}")),
+            Self::ColorTagErr => Some("unable to look for colour tags".to_string()),
+            Self::InvalidMarkerParams(msg) => Some(format!("invalid marker parameters: {msg}")),
+            Self::ArucoUnavailable => Some(
+                "marker decoding unavailable: bombuscv built without the `aruco` feature"
+                    .to_string(),
+            ),
+            Self::MarkerReaderErr => Some("unable to create marker detector".to_string()),
+            Self::MarkerDecodingErr => Some("unable to decode markers".to_string()),
+            Self::InvalidMarkerLog => Some("unable to create marker log file".to_string()),
+            Self::MarkerLogWriteErr => Some("unable to write marker log".to_string()),
+            Self::InvalidClassifierParams(msg) => {
+                Some(format!("invalid classifier parameters: {msg}"))
+            }
+            Self::InvalidLabels => Some("unable to read class labels file".to_string()),
+            Self::InvalidModel => Some("unable to load classification model".to_string()),
+            Self::ClassifierErr => Some("unable to classify motion contours".to_string()),
+            Self::InvalidTrajectories => Some("unable to create trajectories file".to_string()),
+            Self::TrajectoriesWriteErr => Some("unable to write trajectories".to_string()),
+            Self::InvalidMask => Some("unable to load detection mask image".to_string()),
+            Self::BackgroundSubtractorErr => {
+                Some("unable to create background subtractor".to_string())
+            }
+            Self::InvalidEventLog => Some("unable to create event log file".to_string()),
+            Self::EventLogWriteErr => Some("unable to write event log record".to_string()),
+            Self::InvalidTimestamps => {
+                Some("unable to create frame timestamp sidecar file".to_string())
+            }
+            Self::TimestampsWriteErr => Some("unable to write frame timestamp".to_string()),
+            Self::IncompatibleCodec(codec, container) => Some(format!(
+                "{codec} codec can't be stored in {container} container"
+            )),
+            Self::UnsupportedCodec(container) => Some(format!(
+                "no codec available to write {container} video files"
+            )),
         }
         .unwrap_or_default()
         .fmt(f)
@@ -0,0 +1,125 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::Detection;
+
+/// Message passed from the motion detection thread to the frame writer thread.
+#[derive(Debug)]
+pub enum Packet {
+    /// Motion event started: next frames belong to the event.
+    Start,
+    /// Frame to be written, along with its motion detection outcome.
+    Frame(Detection),
+    /// Motion event ended.
+    End,
+}
+
+/// Motion event state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// No motion.
+    Idle,
+    /// Motion detected in the given number of consecutive frames, not enough to start an event.
+    Triggered(u32),
+    /// Event in progress, motion detected in the last frame.
+    Recording,
+    /// Event in progress, no motion detected in the given number of consecutive frames.
+    Cooldown(u32),
+}
+
+/// Event state transition caused by the last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Event state didn't change (or changed without starting/ending an event).
+    None,
+    /// Event started with the last frame.
+    Start,
+    /// Event ended with the last frame (which doesn't belong to the event).
+    End,
+}
+
+/// Motion event state machine (Idle → Triggered → Recording → Cooldown): turns per frame
+/// motion detection into motion events.
+///
+/// # Fields
+/// * state: current event state
+/// * min_frames: minimum consecutive motion frames to start an event
+/// * post_roll: number of frames without motion before the event ends
+#[derive(Debug)]
+pub struct EventStateMachine {
+    state: State,
+    min_frames: u32,
+    post_roll: u32,
+}
+
+impl EventStateMachine {
+    /// Create an instance of the EventStateMachine.
+    ///
+    /// # Parameters
+    /// * min_frames: minimum consecutive motion frames to start an event (0 is treated as 1)
+    /// * post_roll: number of frames without motion recorded after the last motion frame
+    pub fn new(min_frames: u32, post_roll: u32) -> Self {
+        Self {
+            state: State::Idle,
+            min_frames: min_frames.max(1),
+            post_roll,
+        }
+    }
+
+    /// Return the current event state.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Return whether an event is in progress, i.e. the last frame has to be recorded.
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, State::Recording | State::Cooldown(_))
+    }
+
+    /// Update the event state with the motion detection outcome of the next frame.
+    pub fn update(&mut self, motion: bool) -> Transition {
+        let (state, transition) = match (self.state, motion) {
+            // Count consecutive motion frames until an event can start.
+            (State::Idle, true) => self.trigger(1),
+            (State::Triggered(frames), true) => self.trigger(frames + 1),
+            (State::Idle | State::Triggered(_), false) => (State::Idle, Transition::None),
+            // Motion keeps the event going.
+            (State::Recording | State::Cooldown(_), true) => (State::Recording, Transition::None),
+            // Keep recording up to `post_roll` frames without motion.
+            (State::Recording, false) => self.cooldown(1),
+            (State::Cooldown(frames), false) => self.cooldown(frames + 1),
+        };
+
+        self.state = state;
+        transition
+    }
+
+    /// Return the next state given the number of consecutive motion `frames`.
+    fn trigger(&self, frames: u32) -> (State, Transition) {
+        match frames >= self.min_frames {
+            true => (State::Recording, Transition::Start),
+            false => (State::Triggered(frames), Transition::None),
+        }
+    }
+
+    /// Return the next state given the number of consecutive `frames` without motion.
+    fn cooldown(&self, frames: u32) -> (State, Transition) {
+        match frames > self.post_roll {
+            true => (State::Idle, Transition::End),
+            false => (State::Cooldown(frames), Transition::None),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    error::ErrorKind,
+    markers::Marker,
+    tracker::{Crossing, Direction},
+};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Deserialize;
+use std::{fmt::Write as _, fs::File, io::Write, path::Path, str::FromStr};
+
+/// Event log file formats.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// JSON Lines: one JSON object per line.
+    Jsonl,
+    /// Comma separated values, with header.
+    Csv,
+}
+
+impl LogFormat {
+    /// Return the event log file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "events.jsonl",
+            Self::Csv => "events.csv",
+        }
+    }
+
+    /// Return the per-hour boundary crossing counts file extension.
+    pub fn counts_extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "counts.jsonl",
+            Self::Csv => "counts.csv",
+        }
+    }
+
+    /// Return the marker log file extension.
+    pub fn markers_extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "markers.jsonl",
+            Self::Csv => "markers.csv",
+        }
+    }
+
+    /// Return the per-track trajectories file extension.
+    pub fn tracks_extension(&self) -> &'static str {
+        match self {
+            Self::Jsonl => "tracks.jsonl",
+            Self::Csv => "tracks.csv",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!(
+                "invalid event log format '{s}' (valid: jsonl, csv)"
+            )),
+        }
+    }
+}
+
+/// Motion event record.
+///
+/// Motion fields (`start`, `end`, `frames`, `peak_area`) only refer to the motion frames, while
+/// clip fields (`clip_start`, `clip_end`, `first_frame`, `last_frame`) span all the written event
+/// frames, pre-roll & post-roll included.
+///
+/// # Fields
+/// * event: event counter
+/// * start: date&time of the first motion frame
+/// * end: date&time of the last motion frame
+/// * frames: number of motion frames
+/// * peak_area: peak motion area, as fraction of the frame area
+/// * file: output video file holding the event
+/// * clip_start: date&time of the first event frame
+/// * clip_end: date&time of the last event frame
+/// * first_frame: index of the first event frame in `file`
+/// * last_frame: index of the last event frame in `file`
+/// * count_in: number of in boundary crossings during the event
+/// * count_out: number of out boundary crossings during the event
+/// * tags: colour tags found during the event (first found first)
+/// * markers: fiducial marker IDs decoded during the event (first decoded first)
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    pub event: u32,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub frames: u64,
+    pub peak_area: f64,
+    pub file: String,
+    pub clip_start: DateTime<Local>,
+    pub clip_end: DateTime<Local>,
+    pub first_frame: u64,
+    pub last_frame: u64,
+    pub count_in: u32,
+    pub count_out: u32,
+    pub tags: Vec<String>,
+    pub markers: Vec<i32>,
+}
+
+impl EventRecord {
+    /// Create an event record from its first frame (not counted yet, see `push`).
+    ///
+    /// # Parameters
+    /// * event: event counter
+    /// * file: output video file holding the event
+    /// * datetime: first frame date&time
+    /// * index: first frame index in `file`
+    pub fn new(event: u32, file: &str, datetime: DateTime<Local>, index: u64) -> Self {
+        Self {
+            event,
+            start: datetime,
+            end: datetime,
+            frames: 0,
+            peak_area: 0.,
+            file: file.to_string(),
+            clip_start: datetime,
+            clip_end: datetime,
+            first_frame: index,
+            last_frame: index,
+            count_in: 0,
+            count_out: 0,
+            tags: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    /// Update the event record with the next event frame: `motion_area` is `None` for pre-roll
+    /// & post-roll frames (no motion).
+    pub fn push(&mut self, datetime: DateTime<Local>, motion_area: Option<f64>, index: u64) {
+        self.clip_end = datetime;
+        self.last_frame = index;
+
+        if let Some(motion_area) = motion_area {
+            if self.frames == 0 {
+                self.start = datetime;
+            }
+            self.end = datetime;
+            self.frames += 1;
+            self.peak_area = self.peak_area.max(motion_area);
+        }
+    }
+
+    /// Count the boundary `crossings` of an event frame.
+    pub fn count(&mut self, crossings: &[Crossing]) {
+        for crossing in crossings {
+            match crossing.direction {
+                Direction::In => self.count_in += 1,
+                Direction::Out => self.count_out += 1,
+            }
+        }
+    }
+
+    /// Add the colour `tags` found in an event frame.
+    pub fn tag(&mut self, tags: &[String]) {
+        for tag in tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+    }
+
+    /// Add the fiducial `markers` decoded in an event frame.
+    pub fn mark(&mut self, markers: &[Marker]) {
+        for marker in markers {
+            if !self.markers.contains(&marker.id) {
+                self.markers.push(marker.id);
+            }
+        }
+    }
+
+    /// Return the event duration (first to last motion frame) in seconds.
+    pub fn duration(&self) -> f64 {
+        (self.end - self.start).num_milliseconds() as f64 / 1e3
+    }
+
+    /// Return the event record as JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"event\":{},\"start\":\"{}\",\"end\":\"{}\",\"duration\":{:.3},\"frames\":{},\
+             \"peak_area\":{:.6},\"file\":{},\"clip_start\":\"{}\",\"clip_end\":\"{}\",\
+             \"first_frame\":{},\"last_frame\":{},\"in\":{},\"out\":{},\"tags\":[{}],\
+             \"markers\":[{}]}}",
+            self.event,
+            self.start.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.end.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.duration(),
+            self.frames,
+            self.peak_area,
+            json_string(&self.file),
+            self.clip_start
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.clip_end.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.first_frame,
+            self.last_frame,
+            self.count_in,
+            self.count_out,
+            self.tags
+                .iter()
+                .map(|tag| json_string(tag))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.markers
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
+    /// Return the event record as CSV row.
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{:.3},{},{:.6},{},{},{},{},{},{},{},{},{}",
+            self.event,
+            self.start.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.end.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.duration(),
+            self.frames,
+            self.peak_area,
+            csv_field(&self.file),
+            self.clip_start
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.clip_end.to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.first_frame,
+            self.last_frame,
+            self.count_in,
+            self.count_out,
+            // Multiple tags are separated by semicolons.
+            csv_field(&self.tags.join(";")),
+            self.markers
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(";"),
+        )
+    }
+}
+
+/// CSV event log header.
+const CSV_HEADER: &str = "event,start,end,duration,frames,peak_area,file,clip_start,clip_end,\
+                          first_frame,last_frame,in,out,tags,markers";
+
+/// Event log writer: one record per motion event.
+///
+/// # Fields
+/// * file: event log file
+/// * format: event log file format
+#[derive(Debug)]
+pub struct EventLog {
+    file: File,
+    format: LogFormat,
+}
+
+impl EventLog {
+    /// Create the event log file at `path` (writing the header, where relevant).
+    pub fn create(path: &Path, format: LogFormat) -> Result<Self, ErrorKind> {
+        let mut file = File::create(path).map_err(|_| ErrorKind::InvalidEventLog)?;
+        if format == LogFormat::Csv {
+            writeln!(file, "{CSV_HEADER}").map_err(|_| ErrorKind::InvalidEventLog)?;
+        }
+
+        Ok(Self { file, format })
+    }
+
+    /// Append `record` to the event log.
+    pub fn write(&mut self, record: &EventRecord) -> Result<(), ErrorKind> {
+        let mut line = match self.format {
+            LogFormat::Jsonl => record.to_json(),
+            LogFormat::Csv => record.to_csv(),
+        };
+        line.push('\n');
+
+        // Write the whole line at once, so that the log is consistent if execution is interrupted.
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|_| ErrorKind::EventLogWriteErr)
+    }
+}
+
+/// Return `s` as JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+/// Return `s` as CSV field, quoted if needed.
+pub fn csv_field(s: &str) -> String {
+    match s.contains(&[',', '"', '\n', '\r'][..]) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
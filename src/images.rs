@@ -0,0 +1,354 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, Frame};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use glob::Pattern;
+use opencv::{
+    core::Size,
+    imgcodecs::{imread, IMREAD_COLOR},
+    imgproc::{resize, INTER_LINEAR},
+    prelude::{Mat, MatTraitConst},
+};
+use serde::Deserialize;
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Image file extensions picked up from input directories.
+const IMAGE_EXTENSIONS: [&str; 7] = ["bmp", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
+
+/// Image file formats (image sequence output).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpg,
+    Png,
+}
+
+impl ImageFormat {
+    /// Return the image file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpg" | "jpeg" => Ok(Self::Jpg),
+            "png" => Ok(Self::Png),
+            _ => Err(format!("invalid image format '{s}' (valid: jpg, png)")),
+        }
+    }
+}
+
+/// Image sequence input.
+///
+/// # Fields
+/// * files: image files, in sequence order
+/// * next: index of the next image file to be read
+/// * size: frame size (size of the first image: following images are resized to it)
+/// * fps: image sequence framerate (output video framerate)
+#[derive(Debug, Clone)]
+pub struct ImageSequence {
+    files: Vec<PathBuf>,
+    next: usize,
+    size: Size,
+    fps: f64,
+}
+
+impl ImageSequence {
+    /// Open the image sequence `images` (see `image_files`).
+    pub fn open(images: &Path, fps: f64) -> Result<Self, ErrorKind> {
+        let files = image_files(images)?;
+
+        let first = files.first().ok_or(ErrorKind::NoImageInput)?;
+        let size = read_image(first)?
+            .size()
+            .map_err(|_| ErrorKind::InvalidImage)?;
+
+        Ok(Self {
+            files,
+            next: 0,
+            size,
+            fps,
+        })
+    }
+
+    /// Return the frame size.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Return the image sequence framerate.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Return the timestamp of the first image.
+    pub fn start(&self) -> Option<DateTime<Local>> {
+        self.files.first().map(|file| image_datetime(file))
+    }
+
+    /// Read the next image as frame: once the sequence is exhausted an empty frame is returned
+    /// (same as video file input).
+    pub fn read(&mut self) -> Result<Frame, ErrorKind> {
+        let file = match self.files.get(self.next) {
+            Some(file) => file,
+            None => {
+                return Ok(Frame {
+                    frame: Mat::default(),
+                    datetime: Local::now(),
+                })
+            }
+        };
+        self.next += 1;
+
+        let mut frame = read_image(file)?;
+        if frame.size().map_err(|_| ErrorKind::InvalidImage)? != self.size {
+            let mut resized = Mat::default();
+            resize(&frame, &mut resized, self.size, 0., 0., INTER_LINEAR)
+                .map_err(|_| ErrorKind::InvalidImage)?;
+            frame = resized;
+        }
+
+        Ok(Frame {
+            frame,
+            datetime: image_datetime(file),
+        })
+    }
+}
+
+/// Return true if `path` has an image file extension.
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        })
+}
+
+/// Return the image sequence `images` files: either a directory (its image files, in natural
+/// order) or a printf-style pattern (i.e. `IMG_%04d.JPG`, matching files sorted by number).
+pub fn image_files(images: &Path) -> Result<Vec<PathBuf>, ErrorKind> {
+    match images.is_dir() {
+        true => directory_images(images),
+        false => Ok(pattern_images(images)),
+    }
+}
+
+/// Return the image files in `directory`, in natural order (i.e. `IMG_2.JPG` before
+/// `IMG_10.JPG`).
+fn directory_images(directory: &Path) -> Result<Vec<PathBuf>, ErrorKind> {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .map_err(|_| ErrorKind::NoImageInput)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_image(path))
+        .collect();
+    files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    Ok(files)
+}
+
+/// Compare `a` & `b` in natural order: digit runs are compared by their numeric value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    // Split the leading digit run off `s`.
+    fn digits(s: &str) -> (&str, &str) {
+        s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+    }
+    let (mut a, mut b) = (a, b);
+
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let ((x, a_rest), (y, b_rest)) = (digits(a), digits(b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                // Longer numbers are greater; leading zeros break ties.
+                let order = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = a_rest;
+                b = b_rest;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Return the image files matching the printf-style `pattern` (`%d` or `%0<width>d`), sorted by
+/// number (gaps in the numbering are allowed).
+fn pattern_images(pattern: &Path) -> Vec<PathBuf> {
+    let pattern = pattern.to_str().expect("invalid UTF-8 image sequence path");
+    let (prefix, width, suffix) = match parse_pattern(pattern) {
+        Some(parts) => parts,
+        None => return Vec::new(),
+    };
+    let glob = format!("{}*{}", Pattern::escape(prefix), Pattern::escape(suffix));
+    let paths = match glob::glob(&glob) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+
+    // Keep the files whose number (at least `width` digits) sits between prefix & suffix.
+    let mut files: Vec<(u64, PathBuf)> = paths
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let number = path.to_str()?.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            match number.len() >= width.max(1) && number.bytes().all(|b| b.is_ascii_digit()) {
+                true => Some((number.parse().ok()?, path)),
+                false => None,
+            }
+        })
+        .collect();
+    files.sort();
+
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Split printf-style `pattern` into prefix, number width and suffix.
+fn parse_pattern(pattern: &str) -> Option<(&str, usize, &str)> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let width = match &rest[..end] {
+        "" => 0,
+        width => width.parse().ok()?,
+    };
+
+    Some((&pattern[..start], width, &rest[end + 1..]))
+}
+
+/// Read image `file` as BGR frame.
+fn read_image(file: &Path) -> Result<Mat, ErrorKind> {
+    let file = file.to_str().expect("invalid UTF-8 image path");
+    match imread(file, IMREAD_COLOR) {
+        Ok(image) if !image.empty() => Ok(image),
+        _ => Err(ErrorKind::InvalidImage),
+    }
+}
+
+/// Return the date&time image `file` was taken: EXIF original date&time (JPEG), falling back to
+/// the file modification time.
+pub fn image_datetime(file: &Path) -> DateTime<Local> {
+    fs::read(file)
+        .ok()
+        .and_then(|data| exif_datetime(&data))
+        .and_then(|datetime| Local.from_local_datetime(&datetime).earliest())
+        .or_else(|| {
+            let modified = fs::metadata(file).and_then(|meta| meta.modified()).ok()?;
+            Some(DateTime::<Local>::from(modified))
+        })
+        .unwrap_or_else(Local::now)
+}
+
+/// EXIF tags.
+const TAG_DATETIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATETIME_ORIGINAL: u16 = 0x9003;
+
+/// Parse the EXIF date&time (`DateTimeOriginal`, or `DateTime`) from JPEG `data`.
+pub fn exif_datetime(data: &[u8]) -> Option<NaiveDateTime> {
+    let tiff = exif_segment(data)?;
+    let le = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(match le {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match le {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    };
+    // Return the value offset of `tag` in the IFD at `ifd`.
+    let find = |ifd: usize, tag: u16| -> Option<usize> {
+        let entries = u16_at(ifd)? as usize;
+        (0..entries)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|entry| u16_at(*entry) == Some(tag))
+            .map(|entry| entry + 8)
+    };
+    // Parse the ASCII date&time (`YYYY:MM:DD HH:MM:SS`) at value offset `value`.
+    let datetime = |value: usize| -> Option<NaiveDateTime> {
+        let offset = u32_at(value)? as usize;
+        let text = std::str::from_utf8(tiff.get(offset..offset + 19)?).ok()?;
+        NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S").ok()
+    };
+
+    let ifd0 = u32_at(4)? as usize;
+    let original = find(ifd0, TAG_EXIF_IFD)
+        .and_then(u32_at)
+        .and_then(|exif_ifd| find(exif_ifd as usize, TAG_DATETIME_ORIGINAL))
+        .and_then(datetime);
+
+    original.or_else(|| find(ifd0, TAG_DATETIME).and_then(datetime))
+}
+
+/// Return the TIFF structure of the JPEG `data` EXIF segment (APP1).
+fn exif_segment(data: &[u8]) -> Option<&[u8]> {
+    // Start Of Image marker.
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut offset = 2;
+    loop {
+        let marker = data.get(offset..offset + 2)?;
+        // Start Of Scan: no more metadata.
+        if marker[0] != 0xFF || marker[1] == 0xDA {
+            return None;
+        }
+        let length = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]) as usize;
+        let segment = data.get(offset + 4..offset + 2 + length)?;
+
+        if marker[1] == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        offset += 2 + length;
+    }
+}
@@ -0,0 +1,115 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::error::ErrorKind;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Video file extensions picked up from input directories.
+const VIDEO_EXTENSIONS: [&str; 10] = [
+    "avi", "h264", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "webm", "wmv",
+];
+
+/// Suffix appended to the input video file stem to name the batch outputs.
+const BATCH_SUFFIX: &str = "_motion";
+
+/// Return true if `video` is a batch input (a directory or a glob pattern) rather than a single
+/// video file.
+pub fn is_batch(video: &Path) -> bool {
+    !video.is_file()
+}
+
+/// Return true if `path` contains glob pattern special characters.
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(&['*', '?', '['][..])
+}
+
+/// Resolve `video` input to the sorted list of video files to be processed: `video` is either a
+/// video file, a directory (its video files, by extension) or a glob pattern.
+pub fn resolve(video: &Path) -> Result<Vec<PathBuf>, ErrorKind> {
+    let mut inputs = if video.is_file() {
+        vec![video.to_path_buf()]
+    } else if video.is_dir() {
+        fs::read_dir(video)
+            .map_err(|_| ErrorKind::InvalidInput)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_video(path))
+            .collect()
+    } else {
+        let pattern = video.to_str().expect("invalid UTF-8 video path");
+        glob::glob(pattern)
+            .map_err(|_| ErrorKind::InvalidInput)?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file() && is_video(path))
+            .collect()
+    };
+
+    if inputs.is_empty() {
+        return Err(ErrorKind::NoVideoInput);
+    }
+    inputs.sort();
+
+    Ok(inputs)
+}
+
+/// Return true if `path` has a video file extension.
+fn has_video_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        })
+}
+
+/// Return true if `path` has a video file extension and isn't a batch output.
+fn is_video(path: &Path) -> bool {
+    has_video_extension(path) && !is_batch_output(path)
+}
+
+/// Return true if `path` is a batch output video (`<input file name>_motion`, followed by the
+/// event counter in clip mode, and by `.debug` for debug videos).
+fn is_batch_output(path: &Path) -> bool {
+    let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => stem.strip_suffix(".debug").unwrap_or(stem),
+        None => return false,
+    };
+    // Strip the event counter (`_0001`).
+    let stem = match stem.rsplit_once('_') {
+        Some((head, counter))
+            if !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            head
+        }
+        _ => stem,
+    };
+
+    stem.strip_suffix(BATCH_SUFFIX)
+        .map_or(false, |input| has_video_extension(Path::new(input)))
+}
+
+/// Return the output filename format for the batch input `video`: its file name (extension
+/// included, so that inputs differing by extension only don't share outputs) followed by
+/// `_motion`, escaped so that it's taken literally (no date&time specifiers).
+pub fn batch_format(video: &Path) -> String {
+    let name = video
+        .file_name()
+        .expect("video input has no file name")
+        .to_string_lossy();
+
+    format!("{}{BATCH_SUFFIX}", name.replace('%', "%%"))
+}
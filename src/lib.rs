@@ -21,30 +21,73 @@
 //! **Bumblebees** (hence the name).
 
 pub mod args;
+pub mod buffer;
+pub mod classifier;
 pub mod color;
 pub mod config;
 pub mod error;
+pub mod event;
+pub mod eventlog;
+pub mod images;
+pub mod input;
+pub mod markers;
+pub mod output;
+pub mod probe;
+pub mod snapshot;
+pub mod sort;
+pub mod tags;
+pub mod timestamps;
+pub mod tracker;
 
-use crate::error::ErrorKind;
-use chrono::{DateTime, Local};
+use crate::{
+    classifier::Classifier,
+    config::expand_home,
+    error::ErrorKind,
+    images::{ImageFormat, ImageSequence},
+    markers::{Marker, MarkerReader},
+    tags::TagClassifier,
+    tracker::{Counts, Crossing, TrackPoint, Tracker},
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
 use opencv::{
-    core::{absdiff, Point, Scalar, Size, Vector, BORDER_CONSTANT, BORDER_DEFAULT, CV_8UC3},
+    core::{
+        absdiff, bitwise_and, convert_scale_abs, get_build_information, hconcat2, Point, Ptr, Rect,
+        Scalar, Size, Vector, BORDER_CONSTANT, BORDER_DEFAULT, CV_32FC3, CV_8UC1, CV_8UC3,
+    },
+    imgcodecs::{imread, imwrite, IMREAD_GRAYSCALE},
     imgproc::{
-        cvt_color, dilate, find_contours, gaussian_blur, morphology_default_border_value, put_text,
-        resize, threshold, LineTypes, CHAIN_APPROX_SIMPLE, COLOR_BGR2GRAY, FONT_HERSHEY_DUPLEX,
-        INTER_LINEAR, RETR_EXTERNAL, THRESH_BINARY,
+        accumulate_weighted, bounding_rect, contour_area, cvt_color, dilate, fill_poly,
+        find_contours, gaussian_blur, morphology_default_border_value, put_text, rectangle, resize,
+        threshold, LineTypes, CHAIN_APPROX_SIMPLE, COLOR_BGR2GRAY, COLOR_GRAY2BGR,
+        FONT_HERSHEY_DUPLEX, INTER_LINEAR, INTER_NEAREST, RETR_EXTERNAL, THRESH_BINARY,
+    },
+    prelude::{BackgroundSubtractor, Mat, MatTraitConst},
+    video::{
+        create_background_subtractor_knn, create_background_subtractor_mog2,
+        BackgroundSubtractorKNN, BackgroundSubtractorMOG2,
     },
-    prelude::{Mat, MatTraitConst},
     videoio::{
         VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, VideoWriter, VideoWriterTrait,
-        CAP_FFMPEG, CAP_PROP_FPS, CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH, CAP_V4L2,
+        VideoWriterTraitConst, CAP_FFMPEG, CAP_GSTREAMER, CAP_PROP_AUTOFOCUS,
+        CAP_PROP_AUTO_EXPOSURE, CAP_PROP_AUTO_WB, CAP_PROP_BRIGHTNESS, CAP_PROP_CONTRAST,
+        CAP_PROP_EXPOSURE, CAP_PROP_FOCUS, CAP_PROP_FPS, CAP_PROP_FRAME_COUNT,
+        CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH, CAP_PROP_GAIN, CAP_PROP_OPEN_TIMEOUT_MSEC,
+        CAP_PROP_POS_MSEC, CAP_PROP_READ_TIMEOUT_MSEC, CAP_PROP_SATURATION, CAP_PROP_SHARPNESS,
+        CAP_PROP_WB_TEMPERATURE, CAP_V4L2,
     },
 };
 // use opencv::highgui;
-use std::{os::raw::c_char, path::Path};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    fmt, fs, mem,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time,
+};
 
-/// Video codecs.
-#[derive(Debug)]
+/// Video codecs (case-insensitive, both in configuration file and CLI arguments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Codec {
     MJPG,
     XVID,
@@ -53,6 +96,9 @@ pub enum Codec {
 }
 
 impl Codec {
+    /// All video codecs, in fallback order.
+    pub const ALL: [Codec; 4] = [Codec::XVID, Codec::MJPG, Codec::MP4V, Codec::H264];
+
     /// Return the fourcc value associated to the video codec.
     fn fourcc(&self) -> i32 {
         // If no fourcc code can be obtained, video processing can't start, so it's fine to panic.
@@ -74,6 +120,120 @@ impl Codec {
     }
 }
 
+impl Default for Codec {
+    fn default() -> Self {
+        Self::XVID
+    }
+}
+
+impl<'de> Deserialize<'de> for Codec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mjpg" => Ok(Self::MJPG),
+            "xvid" => Ok(Self::XVID),
+            "mp4v" => Ok(Self::MP4V),
+            "h264" => Ok(Self::H264),
+            _ => Err(format!(
+                "invalid codec '{s}' (valid: mjpg, xvid, mp4v, h264)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Video containers (case-insensitive, both in configuration file and CLI arguments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    /// Matroska.
+    Mkv,
+    /// Audio Video Interleave.
+    Avi,
+    /// MPEG-4 Part 14.
+    Mp4,
+}
+
+impl Container {
+    /// Return the container file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Mkv => "mkv",
+            Self::Avi => "avi",
+            Self::Mp4 => "mp4",
+        }
+    }
+
+    /// Return true if `codec` streams can be stored in the container.
+    pub fn supports(&self, codec: Codec) -> bool {
+        match self {
+            Self::Mkv | Self::Avi => true,
+            // MP4 only accepts MPEG-4 Part 2 & H.264 streams tagged as such.
+            Self::Mp4 => matches!(codec, Codec::MP4V | Codec::H264),
+        }
+    }
+
+    /// Return the container compatibility with `codec` as Result.
+    pub fn check(&self, codec: Codec) -> Result<(), ErrorKind> {
+        match self.supports(codec) {
+            true => Ok(()),
+            false => Err(ErrorKind::IncompatibleCodec(codec, *self)),
+        }
+    }
+}
+
+impl Default for Container {
+    fn default() -> Self {
+        Self::Mkv
+    }
+}
+
+impl<'de> Deserialize<'de> for Container {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mkv" => Ok(Self::Mkv),
+            "avi" => Ok(Self::Avi),
+            "mp4" => Ok(Self::Mp4),
+            _ => Err(format!("invalid container '{s}' (valid: mkv, avi, mp4)")),
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.extension().fmt(f)
+    }
+}
+
 /// Captured Frame.
 ///
 /// # Fields
@@ -85,13 +245,245 @@ pub struct Frame {
     pub datetime: DateTime<Local>,
 }
 
+/// Default number of consecutive frame grabbing failures before reconnecting the live input.
+pub const DEFAULT_RECONNECT_AFTER: u32 = 10;
+
+/// Default delay (seconds) before the first reconnection attempt.
+pub const DEFAULT_RECONNECT_BACKOFF: f64 = 1.;
+
+/// Maximum delay (seconds) between reconnection attempts.
+const MAX_RECONNECT_BACKOFF: f64 = 60.;
+
+/// Camera controls (V4L2 properties), applied once the camera is open: unset controls are left
+/// to the driver.
+///
+/// # Fields
+/// * auto_exposure: auto exposure mode (V4L2: 1 manual, 3 aperture priority)
+/// * exposure: exposure (driver units)
+/// * gain: gain
+/// * auto_wb: auto white balance (0 disabled, 1 enabled)
+/// * wb_temperature: white balance temperature (K)
+/// * autofocus: autofocus (0 disabled, 1 enabled)
+/// * focus: focus (driver units)
+/// * brightness: brightness
+/// * contrast: contrast
+/// * saturation: saturation
+/// * sharpness: sharpness
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraControls {
+    pub auto_exposure: Option<f64>,
+    pub exposure: Option<f64>,
+    pub gain: Option<f64>,
+    pub auto_wb: Option<f64>,
+    pub wb_temperature: Option<f64>,
+    pub autofocus: Option<f64>,
+    pub focus: Option<f64>,
+    pub brightness: Option<f64>,
+    pub contrast: Option<f64>,
+    pub saturation: Option<f64>,
+    pub sharpness: Option<f64>,
+}
+
+impl CameraControls {
+    /// Return the controls as (name, VideoCapture property, value) triples: automatic modes come
+    /// first, so that they don't override the manual values.
+    fn properties(&self) -> [(&'static str, i32, Option<f64>); 11] {
+        [
+            ("auto_exposure", CAP_PROP_AUTO_EXPOSURE, self.auto_exposure),
+            ("auto_wb", CAP_PROP_AUTO_WB, self.auto_wb),
+            ("autofocus", CAP_PROP_AUTOFOCUS, self.autofocus),
+            ("exposure", CAP_PROP_EXPOSURE, self.exposure),
+            ("gain", CAP_PROP_GAIN, self.gain),
+            (
+                "wb_temperature",
+                CAP_PROP_WB_TEMPERATURE,
+                self.wb_temperature,
+            ),
+            ("focus", CAP_PROP_FOCUS, self.focus),
+            ("brightness", CAP_PROP_BRIGHTNESS, self.brightness),
+            ("contrast", CAP_PROP_CONTRAST, self.contrast),
+            ("saturation", CAP_PROP_SATURATION, self.saturation),
+            ("sharpness", CAP_PROP_SHARPNESS, self.sharpness),
+        ]
+    }
+
+    /// Apply the controls to `cap`, returning requested & applied value for each of them.
+    fn apply(&self, cap: &mut VideoCapture) -> Vec<AppliedControl> {
+        self.properties()
+            .into_iter()
+            .filter_map(|(name, prop, value)| Some((name, prop, value?)))
+            .map(|(name, prop, requested)| {
+                // The driver may reject or adjust the value: read back the applied one.
+                let set = cap.set(prop, requested).unwrap_or(false);
+                AppliedControl::new(name, requested, set, cap.get(prop).ok())
+            })
+            .collect()
+    }
+}
+
+/// Camera control, as applied by the driver.
+///
+/// # Fields
+/// * name: control name
+/// * requested: requested value
+/// * applied: value read back from the driver (`None` if not supported)
+#[derive(Debug, Clone, Copy)]
+pub struct AppliedControl {
+    pub name: &'static str,
+    pub requested: f64,
+    pub applied: Option<f64>,
+}
+
+impl AppliedControl {
+    /// Create the control outcome from the driver response: whether it accepted the `requested`
+    /// value (`set`) and the value read back from it. The control is unsupported if it can't be
+    /// read back, or if the driver rejected the value and kept a different one.
+    pub fn new(name: &'static str, requested: f64, set: bool, read_back: Option<f64>) -> Self {
+        let applied = read_back.filter(|value| set || *value == requested);
+
+        Self {
+            name,
+            requested,
+            applied,
+        }
+    }
+}
+
+impl fmt::Display for AppliedControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.applied {
+            None => write!(
+                f,
+                "{} unsupported (requested {})",
+                self.name, self.requested
+            ),
+            Some(applied) if applied == self.requested => write!(f, "{} = {applied}", self.name),
+            Some(applied) => write!(
+                f,
+                "{} = {applied} (requested {}, adjusted by the driver)",
+                self.name, self.requested
+            ),
+        }
+    }
+}
+
+/// Camera input parameters, kept to reopen the camera on reconnection.
+///
+/// # Fields
+/// * index: _/dev/video<index>_ capture camera index
+/// * height: video capture desired frame height
+/// * width: video capture desired frame width
+/// * fps: video capture desired framerate
+/// * controls: camera controls
+#[derive(Debug, Clone, Copy)]
+struct Camera {
+    index: i32,
+    height: i32,
+    width: i32,
+    fps: i32,
+    controls: CameraControls,
+}
+
+impl Camera {
+    /// Open the camera VideoCapture and apply the camera controls.
+    fn open(&self) -> Result<VideoCapture, ErrorKind> {
+        // Generate Vector of VideoCapture parameters.
+        let params = Vector::from_slice(&[
+            CAP_PROP_FRAME_WIDTH,
+            self.width,
+            CAP_PROP_FRAME_HEIGHT,
+            self.height,
+            CAP_PROP_FPS,
+            self.fps,
+        ]);
+
+        // Construct the VideoCapture object.
+        match VideoCapture::new_with_params(self.index, CAP_V4L2, &params) {
+            Ok(mut cap) => {
+                self.controls.apply(&mut cap);
+                Ok(cap)
+            }
+            Err(_) => Err(ErrorKind::InvalidCameraIndex),
+        }
+    }
+}
+
+/// Maximum plausible network stream framerate: streams often report bogus values.
+const MAX_STREAM_FPS: f64 = 240.;
+
+/// Network stream input parameters, kept to reopen the stream on reconnection.
+///
+/// # Fields
+/// * url: stream URL (i.e. `rtsp://`, `http://`)
+/// * timeout: open & read timeout
+/// * fps: framerate assumed if the stream doesn't report a valid one
+#[derive(Debug, Clone)]
+struct Stream {
+    url: String,
+    timeout: time::Duration,
+    fps: f64,
+}
+
+impl Stream {
+    /// Open the stream VideoCapture through the FFmpeg backend.
+    fn open(&self) -> Result<VideoCapture, ErrorKind> {
+        let timeout = self.timeout.as_millis().min(i32::MAX as u128) as i32;
+        let params = Vector::from_slice(&[
+            CAP_PROP_OPEN_TIMEOUT_MSEC,
+            timeout,
+            CAP_PROP_READ_TIMEOUT_MSEC,
+            timeout,
+        ]);
+
+        match VideoCapture::from_file_with_params(&self.url, CAP_FFMPEG, &params) {
+            Ok(cap) if cap.is_opened().unwrap_or(false) => Ok(cap),
+            _ => Err(ErrorKind::InvalidStream),
+        }
+    }
+}
+
+/// Video frame grabber input source.
+#[derive(Debug, Clone)]
+enum Source {
+    /// Live camera.
+    Camera(Camera),
+    /// Live network stream.
+    Stream(Stream),
+    /// GStreamer pipeline: the end of stream ends the input, as for video files.
+    Pipeline,
+    /// Video file.
+    File,
+    /// Image sequence: the last image ends the input, as for video files.
+    Images(ImageSequence),
+}
+
+impl Source {
+    /// Return true for live sources, which can be reopened on reconnection.
+    fn is_live(&self) -> bool {
+        matches!(self, Self::Camera(_) | Self::Stream(_))
+    }
+}
+
 /// Video frame grabber.
 ///
 /// # Fields
 /// * cap: OpenCV VideoCapture instance
-/// * quiet: mute standard output
+/// * start: recording start date&time of the video file input (`None` for live input)
+/// * start_fallback: the recording start couldn't be determined, `start` is the processing start
+/// * source: input source parameters
+/// * failures: number of consecutive frame grabbing failures
+/// * reconnect_after: consecutive failures before reconnecting the live input (0 disables)
+/// * reconnect_backoff: initial delay between reconnection attempts
+/// * backoff: delay before the next reconnection attempt
 pub struct Grabber {
     cap: VideoCapture,
+    start: Option<DateTime<Local>>,
+    start_fallback: bool,
+    source: Source,
+    failures: u32,
+    reconnect_after: u32,
+    reconnect_backoff: time::Duration,
+    backoff: time::Duration,
 }
 
 impl Grabber {
@@ -102,51 +494,199 @@ impl Grabber {
     /// * height: video capture desired frame height
     /// * width: video capture desired frame width
     /// * fps: video capture desired framerate
-    /// * quiet: mute stdout output
     ///
     /// # Note
     ///
     /// Wherever the requested video capture parameters (height, width, fps) are not available for
     /// the given video capture device, OpenCV selects the closest available values.
     pub fn new(index: i32, height: i32, width: i32, fps: i32) -> Result<Self, ErrorKind> {
-        // Generate Vector of VideoCapture parameters.
-        let params = Vector::from_slice(&[
-            CAP_PROP_FRAME_WIDTH,
-            width,
-            CAP_PROP_FRAME_HEIGHT,
+        let camera = Camera {
+            index,
             height,
-            CAP_PROP_FPS,
+            width,
             fps,
-        ]);
+            controls: CameraControls::default(),
+        };
 
-        // Construct the VideoCapture object.
-        match VideoCapture::new_with_params(index, CAP_V4L2, &params) {
-            Ok(cap) => Ok(Self { cap }),
-            Err(_) => Err(ErrorKind::InvalidCameraIndex),
+        Ok(Self::with_capture(
+            camera.open()?,
+            None,
+            Source::Camera(camera),
+        ))
+    }
+
+    /// Create an instance of the grabber from a network stream input (RTSP, HTTP, MJPEG over
+    /// HTTP, ...), opened through the FFmpeg backend.
+    ///
+    /// # Parameters
+    /// * url: stream URL
+    /// * timeout: open & read timeout
+    /// * fps: framerate assumed if the stream doesn't report a valid one
+    pub fn from_url(url: &str, timeout: time::Duration, fps: f64) -> Result<Self, ErrorKind> {
+        let stream = Stream {
+            url: url.to_string(),
+            timeout,
+            fps,
+        };
+
+        Ok(Self::with_capture(
+            stream.open()?,
+            None,
+            Source::Stream(stream),
+        ))
+    }
+
+    /// Create an instance of the grabber from a GStreamer pipeline source, ending with `appsink`
+    /// (i.e. `libcamerasrc ! video/x-raw,width=640,height=480 ! videoconvert ! appsink`).
+    pub fn from_pipeline(pipeline: &str) -> Result<Self, ErrorKind> {
+        if !gstreamer_available() {
+            return Err(ErrorKind::GStreamerUnavailable);
+        }
+
+        match VideoCapture::from_file(pipeline, CAP_GSTREAMER) {
+            Ok(cap) if cap.is_opened().unwrap_or(false) => {
+                Ok(Self::with_capture(cap, None, Source::Pipeline))
+            }
+            _ => Err(ErrorKind::InvalidPipeline),
         }
     }
 
+    /// Create an instance of the grabber from an image sequence input: a directory (its image
+    /// files, sorted by name) or a printf-style pattern (i.e. `IMG_%04d.JPG`).
+    ///
+    /// # Parameters
+    /// * images: image sequence directory or pattern
+    /// * fps: image sequence framerate
+    ///
+    /// # Note
+    ///
+    /// Images are timestamped with their EXIF original date&time, falling back to the image file
+    /// modification time.
+    pub fn from_images(images: &Path, fps: f64) -> Result<Self, ErrorKind> {
+        let sequence = ImageSequence::open(images, fps)?;
+        // Images are read directly: the VideoCapture stays unopened.
+        let cap = VideoCapture::default().map_err(|_| ErrorKind::InvalidImage)?;
+
+        Ok(Self::with_capture(
+            cap,
+            sequence.start(),
+            Source::Images(sequence),
+        ))
+    }
+
+    /// Create an instance of the grabber from an open VideoCapture.
+    fn with_capture(cap: VideoCapture, start: Option<DateTime<Local>>, source: Source) -> Self {
+        let reconnect_backoff = time::Duration::from_secs_f64(DEFAULT_RECONNECT_BACKOFF);
+
+        Self {
+            cap,
+            start,
+            start_fallback: false,
+            source,
+            failures: 0,
+            reconnect_after: DEFAULT_RECONNECT_AFTER,
+            reconnect_backoff,
+            backoff: reconnect_backoff,
+        }
+    }
+
+    /// Set camera `controls` (kept on reconnection), returning the values applied by the driver.
+    /// No-op for video file & network stream input.
+    pub fn set_controls(&mut self, controls: CameraControls) -> Vec<AppliedControl> {
+        match &mut self.source {
+            Source::Camera(camera) => {
+                camera.controls = controls;
+                controls.apply(&mut self.cap)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Set live input reconnection policy: reconnect after `after` consecutive frame grabbing
+    /// failures (0 disables reconnection), waiting `backoff` seconds before the first attempt and
+    /// doubling the delay after each failed attempt (up to 60 seconds).
+    pub fn set_reconnect(&mut self, after: u32, backoff: f64) {
+        self.reconnect_after = after;
+        self.reconnect_backoff =
+            time::Duration::from_secs_f64(backoff.clamp(0., MAX_RECONNECT_BACKOFF));
+        self.backoff = self.reconnect_backoff;
+    }
+
     /// Create an instance of the grabber from a video file input.
     ///
     /// # Parameters
     /// * video: path of the video file
-    /// * quiet: mute stdout output
-    pub fn from_file(video: &Path) -> Result<Self, ErrorKind> {
+    /// * start: video recording start date&time
+    /// * format: video filename format (chrono strftime specifiers)
+    ///
+    /// # Note
+    ///
+    /// Frames are timestamped with their position in the video file, relative to the recording
+    /// start date&time. If not given, this is parsed from the video filename using `format`, or
+    /// derived from the video file modification time (taken as the recording end) minus the
+    /// video duration, falling back to the processing start (see `is_start_fallback`).
+    pub fn from_file(
+        video: &Path,
+        start: Option<DateTime<Local>>,
+        format: &str,
+    ) -> Result<Self, ErrorKind> {
         let video_path = video.to_str().expect("invalid UTF-8 video path");
 
-        match VideoCapture::from_file(video_path, CAP_FFMPEG) {
-            Ok(cap) => Ok(Self { cap }),
-            Err(_) => Err(ErrorKind::InvalidVideoFile),
+        let cap = match VideoCapture::from_file(video_path, CAP_FFMPEG) {
+            Ok(cap) => cap,
+            Err(_) => return Err(ErrorKind::InvalidVideoFile),
+        };
+        let mut grabber = Self::with_capture(cap, start, Source::File);
+
+        if grabber.start.is_none() {
+            grabber.start = parse_filename_datetime(video, format).or_else(|| {
+                let modified = fs::metadata(video).and_then(|meta| meta.modified()).ok()?;
+                Some(DateTime::<Local>::from(modified) - grabber.get_duration())
+            });
+            if grabber.start.is_none() {
+                grabber.start = Some(Local::now());
+                grabber.start_fallback = true;
+            }
+        }
+
+        Ok(grabber)
+    }
+
+    /// Return the recording start date&time of the video file input (`None` for live input).
+    pub fn get_start(&self) -> Option<DateTime<Local>> {
+        self.start
+    }
+
+    /// Return true if the video file recording start couldn't be determined (neither given,
+    /// nor parsed from the filename, nor derived from the file modification time): frames are
+    /// timestamped from the processing start.
+    pub fn is_start_fallback(&self) -> bool {
+        self.start_fallback
+    }
+
+    /// Return the video file input duration (zero if unknown or for live input).
+    fn get_duration(&self) -> Duration {
+        let frames = self.cap.get(CAP_PROP_FRAME_COUNT).unwrap_or_default();
+        let fps = self.get_fps();
+        match frames > 0. && fps > 0. {
+            true => Duration::milliseconds((frames * 1e3 / fps) as i64),
+            false => Duration::zero(),
         }
     }
 
     pub fn get_height(&self) -> i32 {
+        if let Source::Images(sequence) = &self.source {
+            return sequence.size().height;
+        }
         self.cap
             .get(CAP_PROP_FRAME_HEIGHT)
             .expect("unable to retrieve capture frame height") as i32
     }
 
     pub fn get_width(&self) -> i32 {
+        if let Source::Images(sequence) = &self.source {
+            return sequence.size().width;
+        }
         self.cap
             .get(CAP_PROP_FRAME_WIDTH)
             .expect("unable to retrieve capture frame width") as i32
@@ -159,26 +699,107 @@ impl Grabber {
 
     /// Return video capture framerate.
     pub fn get_fps(&self) -> f64 {
-        self.cap
+        if let Source::Images(sequence) = &self.source {
+            return sequence.fps();
+        }
+        let fps = self
+            .cap
             .get(CAP_PROP_FPS)
-            .expect("unable to retrieve capture fps")
+            .expect("unable to retrieve capture fps");
+
+        match &self.source {
+            Source::Stream(stream) if !fps.is_finite() || fps <= 0. || fps > MAX_STREAM_FPS => {
+                stream.fps
+            }
+            _ => fps,
+        }
     }
 
     /// Grab video frame from camera and return it.
     pub fn grab(&mut self) -> Result<Frame, ErrorKind> {
+        // Image sequence: frames carry their own timestamp.
+        if let Source::Images(sequence) = &mut self.source {
+            return sequence.read();
+        }
+
         // Capture frame.
         let mut frame = Mat::default();
-        if self.cap.read(&mut frame).is_ok() {
-            Ok(Frame {
-                frame,
-                datetime: Local::now(),
-            })
-        } else {
-            Err(ErrorKind::FrameDropped)
+        match self.cap.read(&mut frame) {
+            Ok(true) => {}
+            // End of the video file: the empty frame stops the pipeline.
+            Ok(false) if !self.source.is_live() => {}
+            _ => {
+                self.failures += 1;
+                return Err(ErrorKind::FrameDropped);
+            }
+        }
+        self.failures = 0;
+
+        let datetime = match self.start {
+            // Video file input: recording start date&time + frame position.
+            Some(start) => {
+                let position = self.cap.get(CAP_PROP_POS_MSEC).unwrap_or_default();
+                start + Duration::milliseconds(position as i64)
+            }
+            None => Local::now(),
+        };
+
+        Ok(Frame { frame, datetime })
+    }
+
+    /// Return true if the live input has to be reconnected: too many consecutive frame grabbing
+    /// failures.
+    pub fn needs_reconnect(&self) -> bool {
+        self.source.is_live() && self.reconnect_after > 0 && self.failures >= self.reconnect_after
+    }
+
+    /// Return the delay to wait before the next reconnection attempt.
+    pub fn reconnect_delay(&self) -> time::Duration {
+        self.backoff
+    }
+
+    /// Release and reopen the live input with the same parameters. On failure, the delay before
+    /// the next attempt is doubled (exponential backoff).
+    pub fn reconnect(&mut self) -> Result<(), ErrorKind> {
+        if !self.source.is_live() {
+            return Ok(());
+        }
+        let _ = self.cap.release();
+
+        let cap = match &self.source {
+            Source::Camera(camera) => camera.open(),
+            Source::Stream(stream) => stream.open(),
+            Source::Pipeline | Source::File | Source::Images(_) => {
+                unreachable!("input is not reconnected")
+            }
+        };
+        match cap {
+            Ok(cap) if cap.is_opened().unwrap_or(false) => {
+                self.cap = cap;
+                self.failures = 0;
+                self.backoff = self.reconnect_backoff;
+                Ok(())
+            }
+            _ => {
+                self.backoff =
+                    (self.backoff * 2).min(time::Duration::from_secs_f64(MAX_RECONNECT_BACKOFF));
+                Err(ErrorKind::ReconnectErr)
+            }
         }
     }
 }
 
+/// Parse date&time from `video` filename (without extension) using `format`: date-only formats
+/// are taken at midnight.
+pub fn parse_filename_datetime(video: &Path, format: &str) -> Option<DateTime<Local>> {
+    let stem = video.file_stem()?.to_str()?;
+    let datetime = NaiveDateTime::parse_from_str(stem, format)
+        .or_else(|_| NaiveDate::parse_from_str(stem, format).map(|date| date.and_hms(0, 0, 0)))
+        .ok()?;
+
+    Local.from_local_datetime(&datetime).earliest()
+}
+
 /// Implement Drop trait for the Grabber struct to release the VideoCapture on Grabber drop.
 impl Drop for Grabber {
     fn drop(&mut self) {
@@ -186,36 +807,726 @@ impl Drop for Grabber {
     }
 }
 
+/// Contour detected in a frame.
+///
+/// # Fields
+/// * area: contour area in detector working frame pixels
+/// * rect: contour bounding rectangle in original frame coordinates
+#[derive(Debug, Clone, Copy)]
+pub struct Blob {
+    pub area: f64,
+    pub rect: Rect,
+}
+
+/// Motion detection outcome for a single frame.
+///
+/// # Fields
+/// * frame: the processed frame
+/// * index: position of the frame in the input (processed frames, starting from 0)
+/// * blobs: contours within the configured area window (motion)
+/// * rejected: contours outside the configured area window (noise, shadows, ...)
+/// * frame_area: detector working frame area in pixels
+/// * mask: binary motion mask at working resolution (if kept by the detector)
+/// * crossings: counting boundary crossings detected in the frame (if tracking)
+/// * tracks: confirmed track positions in the frame (if tracking)
+/// * tags: colour tags found inside the accepted contours (if classifying)
+/// * markers: fiducial markers decoded around the accepted contours (if decoding)
+#[derive(Debug)]
+pub struct Detection {
+    pub frame: Frame,
+    pub index: u64,
+    pub blobs: Vec<Blob>,
+    pub rejected: Vec<Blob>,
+    pub frame_area: f64,
+    pub mask: Option<Mat>,
+    pub crossings: Vec<Crossing>,
+    pub tracks: Vec<TrackPoint>,
+    pub tags: Vec<String>,
+    pub markers: Vec<Marker>,
+}
+
+impl Detection {
+    /// Construct the detection of the accepted contours `blobs` in `frame`, with no rejected
+    /// contours, mask, crossings, tracks, tags or markers.
+    pub fn from_blobs(frame: Frame, index: u64, blobs: Vec<Blob>, frame_area: f64) -> Self {
+        Self {
+            frame,
+            index,
+            blobs,
+            rejected: Vec::new(),
+            frame_area,
+            mask: None,
+            crossings: Vec::new(),
+            tracks: Vec::new(),
+            tags: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    /// Return whether motion was detected in the frame.
+    pub fn is_motion(&self) -> bool {
+        !self.blobs.is_empty()
+    }
+
+    /// Return the total area of the accepted contours as a fraction of the frame area.
+    pub fn motion_area(&self) -> f64 {
+        self.blobs.iter().map(|blob| blob.area).sum::<f64>() / self.frame_area
+    }
+
+    /// Return the debug frame: the frame (left) side by side with the motion mask (right),
+    /// upscaled to the frame size. Return `None` if the detector didn't keep the mask.
+    pub fn debug_frame(&self) -> Option<Result<Frame, ErrorKind>> {
+        let mask = self.mask.as_ref()?;
+        let compose = || -> opencv::Result<Mat> {
+            let mut resized = Mat::default();
+            let mut color = Mat::default();
+            let mut debug = Mat::default();
+            resize(
+                mask,
+                &mut resized,
+                self.frame.frame.size()?,
+                0.,
+                0.,
+                INTER_NEAREST,
+            )?;
+            cvt_color(&resized, &mut color, COLOR_GRAY2BGR, 0)?;
+            hconcat2(&self.frame.frame, &color, &mut debug)?;
+            Ok(debug)
+        };
+
+        Some(
+            compose()
+                .map(|frame| Frame {
+                    frame,
+                    datetime: self.frame.datetime,
+                })
+                .map_err(|_| ErrorKind::DebugFrameErr),
+        )
+    }
+}
+
+/// Default value for motion detection working frame width.
+fn default_detector_width() -> u16 {
+    640
+}
+
+/// Default value for motion detection working frame height.
+fn default_detector_height() -> u16 {
+    480
+}
+
+/// Default value for Gaussian blur kernel size.
+fn default_kernel_size() -> u8 {
+    3
+}
+
+/// Default value for Gaussian kernel standard deviation.
+fn default_sigma() -> f64 {
+    21.
+}
+
+/// Default value for binary threshold.
+fn default_threshold() -> u8 {
+    30
+}
+
+/// Default value for dilation iterations.
+fn default_dilate_iterations() -> u8 {
+    3
+}
+
+/// Contour area bound.
+///
+/// Deserialized from an integer (pixels) or a float (fraction of the frame area), i.e.
+/// `min_area = 120` or `min_area = 0.005`; same rules apply parsing from string.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum AreaBound {
+    /// Area in pixels of the detector working frame.
+    Pixels(u32),
+    /// Area as a fraction (0-1) of the detector working frame area.
+    Fraction(f64),
+}
+
+impl AreaBound {
+    /// Return the area bound in pixels, given the working frame area.
+    pub fn pixels(&self, frame_area: f64) -> f64 {
+        match *self {
+            Self::Pixels(pixels) => pixels.into(),
+            Self::Fraction(fraction) => fraction * frame_area,
+        }
+    }
+}
+
+impl FromStr for AreaBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(pixels) = s.parse::<u32>() {
+            return Ok(Self::Pixels(pixels));
+        }
+
+        match s.parse::<f64>() {
+            Ok(fraction) => Ok(Self::Fraction(fraction)),
+            Err(_) => Err(format!("invalid contour area '{s}'")),
+        }
+    }
+}
+
+/// Time span, either in frames or in seconds.
+///
+/// Deserialized from an integer (frames) or a string with `s` suffix (seconds), i.e.
+/// `pre_roll = 30` or `pre_roll = "1.5s"`; same rules apply parsing from string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Span {
+    /// Number of frames.
+    Frames(u32),
+    /// Number of seconds.
+    Seconds(f64),
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Self::Frames(0)
+    }
+}
+
+impl Span {
+    /// Return the span in frames, given the video framerate.
+    pub fn frames(&self, fps: f64) -> usize {
+        match *self {
+            Self::Frames(frames) => frames as usize,
+            Self::Seconds(seconds) => (seconds * fps).round() as usize,
+        }
+    }
+}
+
+impl FromStr for Span {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(seconds) = s.strip_suffix('s') {
+            return match seconds.trim().parse::<f64>() {
+                Ok(seconds) if seconds.is_finite() && seconds >= 0. => Ok(Self::Seconds(seconds)),
+                _ => Err(format!("invalid number of seconds '{s}'")),
+            };
+        }
+
+        s.parse::<u32>()
+            .map(Self::Frames)
+            .map_err(|_| format!("invalid number of frames '{s}'"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Span {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        /// Raw span representation: integer (frames) or string.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Frames(u32),
+            String(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Frames(frames) => Ok(Self::Frames(frames)),
+            Raw::String(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Detection region in original frame coordinates.
+///
+/// Deserialized from a single-key table, i.e. `{ rect = [x, y, width, height] }` or
+/// `{ polygon = [[x, y], [x, y], [x, y]] }`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    /// Rectangle as `[x, y, width, height]`.
+    Rect([i32; 4]),
+    /// Polygon as list of `[x, y]` vertices.
+    Polygon(Vec<[i32; 2]>),
+}
+
+impl Region {
+    /// Return region vertices, scaled by `scale_x` & `scale_y` factors.
+    fn vertices(&self, scale_x: f64, scale_y: f64) -> Vector<Point> {
+        let scale = |[x, y]: [i32; 2]| {
+            Point::new(
+                (f64::from(x) * scale_x).round() as i32,
+                (f64::from(y) * scale_y).round() as i32,
+            )
+        };
+
+        match self {
+            Self::Rect([x, y, width, height]) => [
+                [*x, *y],
+                [x + width, *y],
+                [x + width, y + height],
+                [*x, y + height],
+            ]
+            .into_iter()
+            .map(scale)
+            .collect(),
+            Self::Polygon(vertices) => vertices.iter().copied().map(scale).collect(),
+        }
+    }
+
+    /// Check region consistency.
+    fn validate(&self) -> Result<(), ErrorKind> {
+        match self {
+            Self::Rect([_, _, width, height]) if *width <= 0 || *height <= 0 => {
+                Err(ErrorKind::InvalidDetectorParams(format!(
+                    "region rectangle must have positive size, found {width}x{height}"
+                )))
+            }
+            Self::Polygon(vertices) if vertices.len() < 3 => {
+                Err(ErrorKind::InvalidDetectorParams(format!(
+                    "region polygon must have at least 3 vertices, found {}",
+                    vertices.len()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Motion detection backend.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Difference between consecutive frames.
+    FrameDiff,
+    /// Difference against an exponentially weighted running average background.
+    RunningAverage,
+    /// OpenCV's Gaussian mixture based background subtractor.
+    Mog2,
+    /// OpenCV's K-nearest neighbours based background subtractor.
+    Knn,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::FrameDiff
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frame-diff" => Ok(Self::FrameDiff),
+            "running-average" => Ok(Self::RunningAverage),
+            "mog2" => Ok(Self::Mog2),
+            "knn" => Ok(Self::Knn),
+            _ => Err(format!(
+                "invalid backend '{s}' (valid: frame-diff, running-average, mog2, knn)"
+            )),
+        }
+    }
+}
+
+/// Default running average background learning rate.
+const DEFAULT_RUNNING_AVERAGE_RATE: f64 = 0.05;
+
+/// Default MOG2 variance threshold (OpenCV default).
+const DEFAULT_MOG2_VAR_THRESHOLD: f64 = 16.;
+
+/// Default KNN squared distance threshold (OpenCV default).
+const DEFAULT_KNN_DIST2_THRESHOLD: f64 = 400.;
+
+/// OpenCV background subtractor.
+enum Subtractor {
+    Mog2(Ptr<dyn BackgroundSubtractorMOG2>),
+    Knn(Ptr<dyn BackgroundSubtractorKNN>),
+}
+
+impl Subtractor {
+    /// Compute the foreground mask of `image` and update the background model.
+    fn apply(&mut self, image: &Mat, fgmask: &mut Mat, learning_rate: f64) -> opencv::Result<()> {
+        match self {
+            Self::Mog2(subtractor) => subtractor.apply(image, fgmask, learning_rate),
+            Self::Knn(subtractor) => subtractor.apply(image, fgmask, learning_rate),
+        }
+    }
+}
+
+impl fmt::Debug for Subtractor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mog2(_) => f.write_str("Mog2"),
+            Self::Knn(_) => f.write_str("Knn"),
+        }
+    }
+}
+
+/// Background model.
+#[derive(Debug)]
+enum Background {
+    /// Previous frame.
+    FrameDiff(Mat),
+    /// Running average background (CV_32FC3).
+    RunningAverage(Mat),
+    /// OpenCV background subtractor.
+    Subtractor(Subtractor),
+}
+
+/// Default value for background subtractor history length.
+fn default_history() -> u32 {
+    500
+}
+
+/// Motion detection parameters.
+///
+/// # Fields
+/// * width: working frame width (grabbed frames are resized to this width before processing)
+/// * height: working frame height (grabbed frames are resized to this height before processing)
+/// * kernel_size: Gaussian blur kernel size (must be odd)
+/// * sigma: Gaussian kernel standard deviation (both in x and y direction)
+/// * threshold: binary threshold value applied to the frame difference (0-255)
+/// * dilate_iterations: number of times dilation is applied to the thresholded frame
+/// * min_area: minimum contour area to be considered motion
+/// * max_area: maximum contour area to be considered motion
+/// * roi: regions of interest (original frame coordinates); if empty the whole frame is watched
+/// * exclude: exclusion zones (original frame coordinates)
+/// * mask: mask image path (white means watch), resized to the working frame size
+/// * backend: motion detection backend
+/// * learning_rate: background model learning rate (`running-average`, `mog2`, `knn`)
+/// * history: number of frames affecting the background model (`mog2`, `knn`)
+/// * var_threshold: variance threshold (`mog2`) or squared distance threshold (`knn`)
+/// * detect_shadows: detect shadows and exclude them from motion (`mog2`, `knn`)
+#[derive(Deserialize, Debug, Clone)]
+pub struct DetectorParams {
+    #[serde(default = "default_detector_width")]
+    pub width: u16,
+    #[serde(default = "default_detector_height")]
+    pub height: u16,
+    #[serde(default = "default_kernel_size")]
+    pub kernel_size: u8,
+    #[serde(default = "default_sigma")]
+    pub sigma: f64,
+    #[serde(default = "default_threshold")]
+    pub threshold: u8,
+    #[serde(default = "default_dilate_iterations")]
+    pub dilate_iterations: u8,
+    #[serde(default)]
+    pub min_area: Option<AreaBound>,
+    #[serde(default)]
+    pub max_area: Option<AreaBound>,
+    #[serde(default)]
+    pub roi: Vec<Region>,
+    #[serde(default)]
+    pub exclude: Vec<Region>,
+    #[serde(default)]
+    pub mask: Option<PathBuf>,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub learning_rate: Option<f64>,
+    #[serde(default = "default_history")]
+    pub history: u32,
+    #[serde(default)]
+    pub var_threshold: Option<f64>,
+    #[serde(default)]
+    pub detect_shadows: bool,
+}
+
+impl Default for DetectorParams {
+    fn default() -> Self {
+        Self {
+            width: default_detector_width(),
+            height: default_detector_height(),
+            kernel_size: default_kernel_size(),
+            sigma: default_sigma(),
+            threshold: default_threshold(),
+            dilate_iterations: default_dilate_iterations(),
+            min_area: None,
+            max_area: None,
+            roi: Vec::new(),
+            exclude: Vec::new(),
+            mask: None,
+            backend: Backend::default(),
+            learning_rate: None,
+            history: default_history(),
+            var_threshold: None,
+            detect_shadows: false,
+        }
+    }
+}
+
+impl DetectorParams {
+    /// Check parameters consistency, return Err describing the first invalid value.
+    ///
+    /// # Note
+    ///
+    /// `threshold` is an `u8`, so it's guaranteed to be in the valid 0-255 range.
+    pub fn validate(&self) -> Result<(), ErrorKind> {
+        if self.width == 0 || self.height == 0 {
+            return Err(ErrorKind::InvalidDetectorParams(
+                "working frame size must be non-zero".to_string(),
+            ));
+        }
+
+        if self.kernel_size % 2 == 0 {
+            return Err(ErrorKind::InvalidDetectorParams(format!(
+                "kernel size must be odd, found {}",
+                self.kernel_size
+            )));
+        }
+
+        if !self.sigma.is_finite() || self.sigma < 0. {
+            return Err(ErrorKind::InvalidDetectorParams(format!(
+                "sigma must be a non-negative number, found {}",
+                self.sigma
+            )));
+        }
+
+        for area in [self.min_area, self.max_area].into_iter().flatten() {
+            if let AreaBound::Fraction(fraction) = area {
+                if !(0. ..=1.).contains(&fraction) {
+                    return Err(ErrorKind::InvalidDetectorParams(format!(
+                        "contour area fraction must be between 0 and 1, found {fraction}"
+                    )));
+                }
+            }
+        }
+
+        if let Some(learning_rate) = self.learning_rate {
+            if !(0. ..=1.).contains(&learning_rate) {
+                return Err(ErrorKind::InvalidDetectorParams(format!(
+                    "learning rate must be between 0 and 1, found {learning_rate}"
+                )));
+            }
+        }
+
+        if self.history == 0 || self.history > i32::MAX as u32 {
+            return Err(ErrorKind::InvalidDetectorParams(format!(
+                "invalid background history length {}",
+                self.history
+            )));
+        }
+
+        if let Some(var_threshold) = self.var_threshold {
+            if !var_threshold.is_finite() || var_threshold <= 0. {
+                return Err(ErrorKind::InvalidDetectorParams(format!(
+                    "background threshold must be a positive number, found {var_threshold}"
+                )));
+            }
+        }
+
+        for region in self.roi.iter().chain(self.exclude.iter()) {
+            region.validate()?;
+        }
+
+        if let (Some(min), Some(max)) = (self.min_area, self.max_area) {
+            let frame_area = f64::from(self.size().area());
+            if min.pixels(frame_area) > max.pixels(frame_area) {
+                return Err(ErrorKind::InvalidDetectorParams(
+                    "minimum contour area exceeds maximum contour area".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the working frame Size.
+    pub fn size(&self) -> Size {
+        Size::new(self.width.into(), self.height.into())
+    }
+}
+
 /// Motion detector.
 ///
 /// # Fields
-/// * prev_frame: previous frame to make comparisons
+/// * params: motion detection parameters
+/// * background: background model, depending on the selected backend
+/// * mask_image: mask image loaded from `params.mask` (grayscale)
+/// * mask: detection mask at working resolution, built from `params` on first frame
+/// * frame_size: original frame size `mask` was built for
+/// * frames: number of processed frames (index of the next frame)
+/// * keep_mask: attach the binary motion mask to detections
+/// * tracker: contour tracker (track IDs, boundary crossings & trajectories)
+/// * tag_classifier: colour tag classifier
+/// * marker_reader: fiducial marker reader
+/// * dnn_classifier: contour classifier rejecting non-bee motion
 #[derive(Debug)]
 pub struct MotionDetector {
-    prev_frame: Mat,
+    params: DetectorParams,
+    background: Background,
+    mask_image: Option<Mat>,
+    mask: Option<Mat>,
+    frame_size: Size,
+    frames: u64,
+    keep_mask: bool,
+    tracker: Option<Tracker>,
+    tag_classifier: Option<TagClassifier>,
+    marker_reader: Option<MarkerReader>,
+    dnn_classifier: Option<Classifier>,
 }
 
 impl Default for MotionDetector {
     fn default() -> Self {
-        Self::new()
+        // Default parameters are always valid, so it's fine to panic.
+        Self::new(DetectorParams::default()).expect("invalid default detector parameters")
     }
 }
 
 impl MotionDetector {
-    /// Create an instance of the MotionDetector.
-    pub fn new() -> Self {
-        Self {
-            // Initialize prev_frame as 640x480 empty frame: next grabbed frames will be
-            // downscaled to this resolution and this initialization must be a valid Size for the
-            // first frame comparison.
-            prev_frame: unsafe { Mat::new_size(Size::new(640, 480), CV_8UC3).unwrap() },
+    /// Create an instance of the MotionDetector, return Err if `params` are not valid.
+    pub fn new(params: DetectorParams) -> Result<Self, ErrorKind> {
+        params.validate()?;
+
+        // Load mask image (white means watch).
+        let mask_image = match &params.mask {
+            Some(path) => {
+                let path = expand_home(path);
+                let image = imread(
+                    path.to_str().expect("invalid UTF-8 mask path"),
+                    IMREAD_GRAYSCALE,
+                );
+                match image {
+                    Ok(image) if !image.empty() => Some(image),
+                    _ => return Err(ErrorKind::InvalidMask),
+                }
+            }
+            None => None,
+        };
+
+        let background = match params.backend {
+            // Initialize prev_frame as empty frame of the working size: next grabbed frames will
+            // be downscaled to this resolution and this initialization must be a valid Size for
+            // the first frame comparison.
+            Backend::FrameDiff => {
+                Background::FrameDiff(unsafe { Mat::new_size(params.size(), CV_8UC3).unwrap() })
+            }
+            // Background model is initialized with the first frame.
+            Backend::RunningAverage => Background::RunningAverage(Mat::default()),
+            Backend::Mog2 => Background::Subtractor(Subtractor::Mog2(
+                create_background_subtractor_mog2(
+                    params.history as i32,
+                    params.var_threshold.unwrap_or(DEFAULT_MOG2_VAR_THRESHOLD),
+                    params.detect_shadows,
+                )
+                .map_err(|_| ErrorKind::BackgroundSubtractorErr)?,
+            )),
+            Backend::Knn => Background::Subtractor(Subtractor::Knn(
+                create_background_subtractor_knn(
+                    params.history as i32,
+                    params.var_threshold.unwrap_or(DEFAULT_KNN_DIST2_THRESHOLD),
+                    params.detect_shadows,
+                )
+                .map_err(|_| ErrorKind::BackgroundSubtractorErr)?,
+            )),
+        };
+
+        Ok(Self {
+            params,
+            background,
+            mask_image,
+            mask: None,
+            frame_size: Size::default(),
+            frames: 0,
+            keep_mask: false,
+            tracker: None,
+            tag_classifier: None,
+            marker_reader: None,
+            dnn_classifier: None,
+        })
+    }
+
+    /// Attach the binary motion mask (thresholded, dilated & masked difference at working
+    /// resolution) to detections, i.e. for the debug video output.
+    pub fn keep_mask(&mut self, keep: bool) {
+        self.keep_mask = keep;
+    }
+
+    /// Track the motion contours of every processed frame with `tracker`, recording track
+    /// positions & boundary crossings in detections.
+    pub fn set_tracker(&mut self, tracker: Tracker) {
+        self.tracker = Some(tracker);
+    }
+
+    /// Look for colour tags inside the motion contours of every processed frame with
+    /// `tag_classifier`, recording the found tags in detections.
+    pub fn set_tag_classifier(&mut self, tag_classifier: TagClassifier) {
+        self.tag_classifier = Some(tag_classifier);
+    }
+
+    /// Decode the fiducial markers around the motion contours of every processed frame with
+    /// `marker_reader`, recording the decoded markers in detections.
+    pub fn set_marker_reader(&mut self, marker_reader: MarkerReader) {
+        self.marker_reader = Some(marker_reader);
+    }
+
+    /// Classify the contours within the area window with `dnn_classifier`, rejecting those not
+    /// allowed (i.e. ants, flies, leaves, shadows): only the accepted contours count as motion.
+    pub fn set_dnn_classifier(&mut self, dnn_classifier: Classifier) {
+        self.dnn_classifier = Some(dnn_classifier);
+    }
+
+    /// Return the per-hour boundary crossing counts (`None` if not tracking).
+    pub fn counts(&self) -> Option<&Counts> {
+        self.tracker.as_ref().map(Tracker::counts)
+    }
+
+    /// Append the `detection` track positions to the tracker trajectories file (if any).
+    pub fn log_tracks(&mut self, detection: &Detection) -> Result<(), ErrorKind> {
+        match self.tracker.as_mut() {
+            Some(tracker) => tracker.log(detection),
+            None => Ok(()),
+        }
+    }
+
+    /// Build the detection mask at working resolution, given the original `frame_size`: regions
+    /// of interest are white (watch), exclusion zones & black mask image pixels are black.
+    /// Return `None` if no region of interest, exclusion zone or mask image is configured.
+    fn build_mask(&self, frame_size: Size) -> Option<Mat> {
+        if self.params.roi.is_empty() && self.params.exclude.is_empty() && self.mask_image.is_none()
+        {
+            return None;
+        }
+
+        let size = self.params.size();
+        // Scale factors from original frame to working frame coordinates.
+        let scale_x = f64::from(size.width) / f64::from(frame_size.width);
+        let scale_y = f64::from(size.height) / f64::from(frame_size.height);
+
+        // No regions of interest means the whole frame is watched.
+        let background = match self.params.roi.is_empty() {
+            true => 255.,
+            false => 0.,
+        };
+        let mut mask = Mat::new_size_with_default(size, CV_8UC1, Scalar::all(background))
+            .expect("unable to create detection mask");
+        fill_regions(&mut mask, &self.params.roi, scale_x, scale_y, 255.);
+        fill_regions(&mut mask, &self.params.exclude, scale_x, scale_y, 0.);
+
+        if let Some(image) = &self.mask_image {
+            let mut resized = Mat::default();
+            let mut binary = Mat::default();
+            let mut combined = Mat::default();
+
+            resize(image, &mut resized, size, 0., 0., INTER_NEAREST).expect("mask resizing failed");
+            threshold(&resized, &mut binary, 127., 255., THRESH_BINARY).expect("threshold failed");
+            bitwise_and(&mask, &binary, &mut combined, &Mat::default())
+                .expect("bitwise_and failed");
+            mask = combined;
         }
+
+        Some(mask)
     }
 
     /// Receive grabbed frame and detect motion and returns:
-    /// - `Ok`: if `Some(Frame)` motion detected; if `None` no motion detected.
-    /// - `Err`: `frame` was empty and could not be processed.
-    pub fn detect_motion(&mut self, frame: Frame) -> Result<Option<Frame>, ErrorKind> {
+    /// - `Ok`: `Detection` holding the frame and both the accepted & rejected contours (motion
+    ///   is detected if any contour falls within the configured area window).
+    /// - `Err`: `frame` was empty and could not be processed (`ErrorKind::EmptyFrame`), or an
+    ///   analysis stage (colour tags, markers, classifier) failed on it.
+    pub fn detect_motion(&mut self, frame: Frame) -> Result<Detection, ErrorKind> {
         // Create the resized_frame.
         let mut resized_frame = Mat::default();
 
@@ -230,58 +1541,84 @@ impl MotionDetector {
         if frame.frame.empty() {
             return Err(ErrorKind::EmptyFrame);
         }
+        let index = self.frames;
+        self.frames += 1;
+
+        // (Re)build the detection mask whenever the original frame size changes.
+        let frame_size = frame.frame.size().expect("unable to retrieve frame size");
+        if frame_size != self.frame_size {
+            self.mask = self.build_mask(frame_size);
+            self.frame_size = frame_size;
+        }
 
-        // Downscale input frame (to 640x480) to reduce noise & computational weight.
+        // Downscale input frame (to working size) to reduce noise & computational weight.
         resize(
             &frame.frame,
             &mut resized_frame,
             // WARNING: check if chaning the aspect ratio causes any problem.
-            Size::new(640, 480),
+            self.params.size(),
             0.,
             0.,
             INTER_LINEAR,
         )
         .expect("frame resizing failed");
 
-        // Calculate absolute difference of pixel values.
-        absdiff(&self.prev_frame, &resized_frame, &mut frame_one).expect("absdiff failed");
+        // Compute the binary foreground (motion) frame using the selected backend.
+        match &mut self.background {
+            Background::FrameDiff(prev_frame) => {
+                // Calculate absolute difference of pixel values.
+                absdiff(prev_frame, &resized_frame, &mut frame_one).expect("absdiff failed");
 
-        // HELP: this are for graphical example
-        // highgui::imshow("bombuscv", &frame_one).unwrap();
-        // highgui::wait_key(1).unwrap();
+                // HELP: this are for graphical example
+                // highgui::imshow("bombuscv", &frame_one).unwrap();
+                // highgui::wait_key(1).unwrap();
 
-        // Update the previous frame.
-        self.prev_frame = resized_frame;
+                // Update the previous frame.
+                *prev_frame = resized_frame;
 
-        // Convert from BGR colorspace to grayscale.
-        cvt_color(
-            &frame_one,
-            &mut frame_two,
-            COLOR_BGR2GRAY, // Color space conversion code (see #ColorConversionCodes).
-            0, // Number of channels in the destination image; if the parameter is 0, the number of the channels is derived automatically from src and code.
-        )
-        .expect("cvt_color failed");
+                threshold_difference(&self.params, &frame_one, &mut frame_two);
+            }
+            Background::RunningAverage(average) => {
+                // Initialize the background model with the first frame.
+                if average.empty() {
+                    resized_frame
+                        .convert_to(average, CV_32FC3, 1., 0.)
+                        .expect("convert_to failed");
+                }
 
-        // Apply gaussian blur.
-        gaussian_blur(
-            &frame_two,
-            &mut frame_one,
-            Size::new(3, 3), // Kernel Size.
-            21.,             // Gaussian kernel standard deviation in x direction.
-            21.,             // Gaussian kernel standard deviation in y direction.
-            BORDER_DEFAULT,
-        )
-        .expect("gaussian_blur failed");
+                // Calculate absolute difference of pixel values against the background.
+                convert_scale_abs(average, &mut frame_two, 1., 0.)
+                    .expect("convert_scale_abs failed");
+                absdiff(&frame_two, &resized_frame, &mut frame_one).expect("absdiff failed");
 
-        // Apply threshold.
-        threshold(
-            &frame_one,
-            &mut frame_two,
-            30.,           // Threshold value.
-            255., // Maximum value to use with the #THRESH_BINARY and #THRESH_BINARY_INV thresholding types.
-            THRESH_BINARY, // Thresholding type (see #ThresholdType).
-        )
-        .expect("threshold failed");
+                // Update the background model.
+                accumulate_weighted(
+                    &resized_frame,
+                    average,
+                    self.params
+                        .learning_rate
+                        .unwrap_or(DEFAULT_RUNNING_AVERAGE_RATE),
+                    &Mat::default(),
+                )
+                .expect("accumulate_weighted failed");
+
+                threshold_difference(&self.params, &frame_one, &mut frame_two);
+            }
+            Background::Subtractor(subtractor) => {
+                // Negative learning rate means automatically chosen by OpenCV.
+                subtractor
+                    .apply(
+                        &resized_frame,
+                        &mut frame_one,
+                        self.params.learning_rate.unwrap_or(-1.),
+                    )
+                    .expect("background subtraction failed");
+
+                // Foreground pixels are 255, shadows (if detected) are 127: keep foreground only.
+                threshold(&frame_one, &mut frame_two, 127., 255., THRESH_BINARY)
+                    .expect("threshold failed");
+            }
+        }
 
         // Dilate image.
         dilate(
@@ -289,12 +1626,25 @@ impl MotionDetector {
             &mut frame_one,
             &Mat::default(), // Structuring element used for dilation; If elemenat=Mat(), a 3 x 3 rectangular structuring element is used.
             Point::new(-1, -1), // Position of the anchor within the element; default value (-1, -1) means that the anchor is at the element center.
-            3,                  // Number of times dilation is applied.
+            self.params.dilate_iterations.into(), // Number of times dilation is applied.
             BORDER_CONSTANT,    // Pixel extrapolation method, see #BorderTypes.
             morphology_default_border_value().unwrap(), // Border value in case of a constant border.
         )
         .expect("dilate failed");
 
+        // Apply detection mask.
+        if let Some(mask) = &self.mask {
+            bitwise_and(&frame_one, mask, &mut frame_two, &Mat::default())
+                .expect("bitwise_and failed");
+            mem::swap(&mut frame_one, &mut frame_two);
+        }
+
+        // Copy the motion mask before contours are searched in it.
+        let mask = match self.keep_mask {
+            true => Some(frame_one.try_clone().expect("motion mask copy failed")),
+            false => None,
+        };
+
         // Find contours.
         find_contours(
             &frame_one,
@@ -305,26 +1655,173 @@ impl MotionDetector {
         )
         .expect("find_contours failed");
 
-        // Count contours in the processed frame.
-        Ok(match contours.is_empty() {
-            // No motion was detected.
-            true => None,
-            // Motion was found, return original video frame.
-            false => Some(frame),
-        })
+        // Working frame area & scale factors from working frame to original frame coordinates.
+        let frame_area = f64::from(self.params.size().area());
+        let scale_x = f64::from(frame.frame.cols()) / f64::from(self.params.width);
+        let scale_y = f64::from(frame.frame.rows()) / f64::from(self.params.height);
+
+        // Contour area window in working frame pixels.
+        let min_area = self
+            .params
+            .min_area
+            .map_or(0., |min| min.pixels(frame_area));
+        let max_area = self
+            .params
+            .max_area
+            .map_or(f64::INFINITY, |max| max.pixels(frame_area));
+
+        // Filter contours by area: only blobs within the area window count as motion.
+        let mut blobs = Vec::new();
+        let mut rejected = Vec::new();
+        for contour in contours.iter() {
+            let blob = Blob {
+                area: contour_area(&contour, false).expect("contour_area failed"),
+                rect: scale_rect(
+                    bounding_rect(&contour).expect("bounding_rect failed"),
+                    scale_x,
+                    scale_y,
+                ),
+            };
+
+            if (min_area..=max_area).contains(&blob.area) {
+                blobs.push(blob);
+            } else {
+                rejected.push(blob);
+            }
+        }
+
+        // Second stage: reject the contours not recognized as allowed classes.
+        if let Some(dnn_classifier) = self.dnn_classifier.as_mut() {
+            if !blobs.is_empty() {
+                let (accepted, mut not_allowed) = dnn_classifier.classify(&frame.frame, blobs)?;
+                blobs = accepted;
+                rejected.append(&mut not_allowed);
+            }
+        }
+
+        let mut detection = Detection {
+            frame,
+            index,
+            blobs,
+            rejected,
+            frame_area,
+            mask,
+            crossings: Vec::new(),
+            tracks: Vec::new(),
+            tags: Vec::new(),
+            markers: Vec::new(),
+        };
+
+        // Match contours to tracks, detecting boundary crossings.
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.update(&mut detection);
+        }
+
+        // Identify marked individuals by their colour tags.
+        if let Some(tag_classifier) = &self.tag_classifier {
+            detection.tags = tag_classifier.classify(&detection.frame.frame, &detection.blobs)?;
+        }
+
+        // Identify tagged individuals by their fiducial markers.
+        if let Some(marker_reader) = &self.marker_reader {
+            detection.markers = marker_reader.read(&detection.frame.frame, &detection.blobs)?;
+        }
+
+        Ok(detection)
     }
 }
 
+/// Convert the BGR `difference` frame to grayscale, blur and threshold it into `output`.
+fn threshold_difference(params: &DetectorParams, difference: &Mat, output: &mut Mat) {
+    let mut gray = Mat::default();
+    let mut blurred = Mat::default();
+
+    // Convert from BGR colorspace to grayscale.
+    cvt_color(
+        difference,
+        &mut gray,
+        COLOR_BGR2GRAY, // Color space conversion code (see #ColorConversionCodes).
+        0, // Number of channels in the destination image; if the parameter is 0, the number of the channels is derived automatically from src and code.
+    )
+    .expect("cvt_color failed");
+
+    // Apply gaussian blur.
+    gaussian_blur(
+        &gray,
+        &mut blurred,
+        // Kernel Size.
+        Size::new(params.kernel_size.into(), params.kernel_size.into()),
+        params.sigma, // Gaussian kernel standard deviation in x direction.
+        params.sigma, // Gaussian kernel standard deviation in y direction.
+        BORDER_DEFAULT,
+    )
+    .expect("gaussian_blur failed");
+
+    // Apply threshold.
+    threshold(
+        &blurred,
+        output,
+        params.threshold.into(), // Threshold value.
+        255., // Maximum value to use with the #THRESH_BINARY and #THRESH_BINARY_INV thresholding types.
+        THRESH_BINARY, // Thresholding type (see #ThresholdType).
+    )
+    .expect("threshold failed");
+}
+
+/// Fill `regions` (scaled by `scale_x` & `scale_y` factors) in `mask` with `value`.
+fn fill_regions(mask: &mut Mat, regions: &[Region], scale_x: f64, scale_y: f64, value: f64) {
+    // One region at a time: filling all polygons at once would leave their overlaps unfilled
+    // (even-odd rule).
+    for region in regions {
+        let polygon: Vector<Vector<Point>> = Vector::from_iter([region.vertices(scale_x, scale_y)]);
+        fill_poly(
+            mask,
+            &polygon,
+            Scalar::all(value),
+            LineTypes::LINE_8 as i32,
+            0,
+            Point::new(0, 0),
+        )
+        .expect("fill_poly failed");
+    }
+}
+
+/// Scale `rect` by `scale_x` & `scale_y` factors.
+fn scale_rect(rect: Rect, scale_x: f64, scale_y: f64) -> Rect {
+    Rect::new(
+        (f64::from(rect.x) * scale_x).round() as i32,
+        (f64::from(rect.y) * scale_y).round() as i32,
+        (f64::from(rect.width) * scale_x).round() as i32,
+        (f64::from(rect.height) * scale_y).round() as i32,
+    )
+}
+
+/// Video frame writer output sink.
+enum Sink {
+    /// OpenCV VideoWriter.
+    Video(VideoWriter),
+    /// Image sequence: one image file per frame, named after its date&time.
+    Images(ImageFormat),
+}
+
 /// Video frame writer.
 ///
 /// # Fields
-/// * writer: OpenCV
+/// * writer: output sink
+/// * path: output video file path (output directory for image sequences)
+/// * frames: number of frames written
 /// * overlay: date&time video overlay
-/// * quiet: mute standard output
+/// * overlay_border: date&time video overlay border
+/// * debug_overlay: motion debug overlay (contour bounding rectangles, motion area & frame index)
+/// * track_overlay: track overlay (track bounding rectangles & IDs)
 pub struct Writer {
-    writer: VideoWriter,
+    writer: Sink,
+    path: String,
+    frames: u64,
     overlay: bool,
     overlay_border: u8,
+    debug_overlay: bool,
+    track_overlay: bool,
 }
 
 impl Writer {
@@ -345,16 +1842,189 @@ impl Writer {
         overlay_border: u8,
     ) -> Result<Self, ErrorKind> {
         // Construct the VideoWriter object.
-        match VideoWriter::new(video_path, codec.fourcc(), fps, size, true) {
-            Ok(writer) => Ok(Self {
-                writer,
+        let writer = VideoWriter::new(video_path, codec.fourcc(), fps, size, true);
+        Self::with_writer(writer, video_path, overlay, overlay_border)
+    }
+
+    /// Create an instance of the writer from a GStreamer pipeline sink (i.e. `appsrc !
+    /// videoconvert ! v4l2h264enc ! h264parse ! matroskamux ! filesink location=out.mkv`).
+    ///
+    /// # Parameters
+    /// * pipeline: GStreamer pipeline, starting with `appsrc`
+    /// * video_path: output video file path written by the pipeline
+    /// * fps: video framerate
+    /// * size: video frame
+    /// * overlay: date and time video overlay
+    /// * overlay_border: date and time video overlay border
+    pub fn from_pipeline(
+        pipeline: &str,
+        video_path: &str,
+        fps: f64,
+        size: Size,
+        overlay: bool,
+        overlay_border: u8,
+    ) -> Result<Self, ErrorKind> {
+        if !gstreamer_available() {
+            return Err(ErrorKind::GStreamerUnavailable);
+        }
+
+        // Encoding is up to the pipeline: no fourcc.
+        let writer = VideoWriter::new_with_backend(pipeline, CAP_GSTREAMER, 0, fps, size, true);
+        Self::with_writer(writer, video_path, overlay, overlay_border)
+    }
+
+    /// Create an instance of the writer saving each frame as image file in `directory`, named
+    /// after the frame date&time (i.e. `20220627T153000.125.jpg`).
+    ///
+    /// # Parameters
+    /// * directory: output directory (created if missing)
+    /// * format: image file format
+    /// * overlay: date and time overlay
+    /// * overlay_border: date and time overlay border
+    pub fn images(
+        directory: &str,
+        format: ImageFormat,
+        overlay: bool,
+        overlay_border: u8,
+    ) -> Result<Self, ErrorKind> {
+        if fs::create_dir_all(directory).is_err() {
+            return Err(ErrorKind::InvalidOutput);
+        }
+
+        Ok(Self {
+            writer: Sink::Images(format),
+            path: directory.to_string(),
+            frames: 0,
+            overlay,
+            overlay_border,
+            debug_overlay: false,
+            track_overlay: false,
+        })
+    }
+
+    /// Create an instance of the writer from the VideoWriter construction outcome.
+    fn with_writer(
+        writer: opencv::Result<VideoWriter>,
+        video_path: &str,
+        overlay: bool,
+        overlay_border: u8,
+    ) -> Result<Self, ErrorKind> {
+        // VideoWriter construction succeeds even if the OpenCV build can't encode the codec.
+        match writer {
+            Ok(writer) if writer.is_opened().unwrap_or(false) => Ok(Self {
+                writer: Sink::Video(writer),
+                path: video_path.to_string(),
+                frames: 0,
                 overlay,
                 overlay_border,
+                debug_overlay: false,
+                track_overlay: false,
             }),
-            Err(_) => Err(ErrorKind::InvalidOutput),
+            _ => Err(ErrorKind::InvalidOutput),
         }
     }
 
+    /// Enable the motion debug overlay, printed by `write_detection`: bounding rectangles of the
+    /// accepted (green) & rejected (red) contours, motion area percentage and frame index.
+    pub fn set_debug_overlay(&mut self, debug_overlay: bool) {
+        self.debug_overlay = debug_overlay;
+    }
+
+    /// Enable the track overlay, printed by `write_detection`: bounding rectangles & IDs of the
+    /// confirmed tracks (yellow).
+    pub fn set_track_overlay(&mut self, track_overlay: bool) {
+        self.track_overlay = track_overlay;
+    }
+
+    /// Return output video file path.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Return the number of frames written to the output video file.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Write the detection frame to the video file, with the motion debug & track overlays if
+    /// enabled.
+    pub fn write_detection(&mut self, mut detection: Detection) -> Result<(), ErrorKind> {
+        if self.debug_overlay {
+            let text = format!(
+                "#{} {:.2}%",
+                detection.index,
+                detection.motion_area() * 100.
+            );
+            let frame = &mut detection.frame.frame;
+            let boxes = detection
+                .blobs
+                .iter()
+                .map(|blob| (blob.rect, Scalar::new(0., 255., 0., 0.)))
+                .chain(
+                    detection
+                        .rejected
+                        .iter()
+                        .map(|blob| (blob.rect, Scalar::new(0., 0., 255., 0.))),
+                );
+            for (rect, color) in boxes {
+                if rectangle(frame, rect, color, 2, LineTypes::LINE_8 as i32, 0).is_err() {
+                    return Err(ErrorKind::TextOverlayErr);
+                }
+            }
+
+            // Text border, then text body, below the date&time overlay.
+            for (color, thickness) in [(0., 2 + i32::from(self.overlay_border)), (255., 2)] {
+                let result = put_text(
+                    frame,
+                    &text,
+                    Point::new(10, 80), // Bottom-left corner of the text string in the image.
+                    FONT_HERSHEY_DUPLEX, // Font type, see #hersheyfonts.
+                    1., // Font scale factor that is multiplied by the font-specific base size.
+                    Scalar::new(color, color, color, 1.), // Text color.
+                    thickness, // Thickness.
+                    LineTypes::LINE_8 as i32, // Linetype.
+                    false,
+                );
+                if result.is_err() {
+                    return Err(ErrorKind::TextOverlayErr);
+                }
+            }
+        }
+
+        if self.track_overlay {
+            let frame = &mut detection.frame.frame;
+            let color = Scalar::new(0., 255., 255., 0.);
+            for point in &detection.tracks {
+                let rect = point.rect;
+                if rectangle(frame, rect, color, 2, LineTypes::LINE_8 as i32, 0).is_err() {
+                    return Err(ErrorKind::TextOverlayErr);
+                }
+
+                // Track ID above the bounding rectangle (inside it, at the top of the frame).
+                let origin = match rect.y >= 26 {
+                    true => Point::new(rect.x, rect.y - 6),
+                    false => Point::new(rect.x, rect.y + 20),
+                };
+                let result = put_text(
+                    frame,
+                    &format!("#{}", point.track),
+                    origin,              // Bottom-left corner of the text string in the image.
+                    FONT_HERSHEY_DUPLEX, // Font type, see #hersheyfonts.
+                    0.6,   // Font scale factor that is multiplied by the font-specific base size.
+                    color, // Text color.
+                    1,     // Thickness.
+                    LineTypes::LINE_8 as i32, // Linetype.
+                    false,
+                );
+                if result.is_err() {
+                    return Err(ErrorKind::TextOverlayErr);
+                }
+            }
+        }
+
+        self.write(detection.frame)
+    }
+
     /// Write passed frame to the video file.
     pub fn write(&mut self, mut frame: Frame) -> Result<(), ErrorKind> {
         // Add date&time overlay.
@@ -394,20 +2064,62 @@ impl Writer {
             }
         }
 
-        // Write frame to video file.
-        if self.writer.write(&frame.frame).is_err() {
-            return Err(ErrorKind::FrameDropped);
+        match &mut self.writer {
+            // Write frame to video file.
+            Sink::Video(writer) => {
+                if writer.write(&frame.frame).is_err() {
+                    return Err(ErrorKind::FrameDropped);
+                }
+            }
+            // Save frame to image file.
+            Sink::Images(format) => {
+                let path = image_path(&self.path, &frame.datetime, *format);
+                let path = path.to_str().expect("invalid UTF-8 image path");
+                if !imwrite(path, &frame.frame, &Vector::new()).unwrap_or(false) {
+                    return Err(ErrorKind::ImageWriteErr(path.to_string()));
+                }
+            }
         }
+        self.frames += 1;
 
         Ok(())
     }
 }
 
+/// Return the image file path in `directory` for a frame captured at `datetime`: frames sharing
+/// the same timestamp get a counter suffix.
+fn image_path(directory: &str, datetime: &DateTime<Local>, format: ImageFormat) -> PathBuf {
+    let name = datetime.format("%Y%m%dT%H%M%S%.3f").to_string();
+    let path =
+        |suffix: &str| Path::new(directory).join(format!("{name}{suffix}.{}", format.extension()));
+
+    (1..)
+        .map(|n| match n {
+            1 => path(""),
+            n => path(&format!("_{n}")),
+        })
+        .find(|path| !path.exists())
+        .expect("unable to find available image path")
+}
+
+/// Return true if OpenCV was built with GStreamer support (Video I/O section of the OpenCV build
+/// information).
+pub fn gstreamer_available() -> bool {
+    get_build_information()
+        .map(|info| {
+            info.lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with("GStreamer:"))
+                .any(|line| line.contains("YES"))
+        })
+        .unwrap_or(false)
+}
+
 /// Implement Drop trait for the Writer struct to release the VideoWriter on Writer drop.
 impl Drop for Writer {
     fn drop(&mut self) {
-        self.writer
-            .release()
-            .expect("unable to release VideoWriter");
+        if let Sink::Video(writer) = &mut self.writer {
+            writer.release().expect("unable to release VideoWriter");
+        }
     }
 }
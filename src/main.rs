@@ -18,27 +18,83 @@
 mod test;
 
 use bombuscv_rs::{
-    args::{Args, Parser},
+    args::{Args, Command, Parser, ProbeArgs},
+    buffer::PreRoll,
+    classifier::Classifier,
     color::{Colorizer, MsgType},
     config::Config,
-    Codec, Grabber, MotionDetector, Writer,
+    error::ErrorKind,
+    event::{EventStateMachine, Packet, Transition},
+    eventlog::EventLog,
+    gstreamer_available, input,
+    markers::{MarkerLog, MarkerReader},
+    output::{Output, Recorder},
+    probe,
+    tags::TagClassifier,
+    tracker::{Counts, Tracker, Trajectories},
+    Detection, Grabber, MotionDetector,
 };
 use chrono::Local;
 use signal_hook::{consts::SIGINT, flag::register};
 use std::io;
 use std::{
-    path::Path,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
     process,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        mpsc, Arc, Mutex,
     },
     thread,
+    time::{Duration, Instant},
 };
 
+/// SIGINT polling interval while waiting to reconnect the live input.
+const RECONNECT_POLL: Duration = Duration::from_millis(100);
+
+/// Processed frames statistics.
+///
+/// # Fields
+/// * total: number of processed frames
+/// * kept: number of frames written to the video output
+/// * counts: per-hour boundary crossing counts (if tracking)
+#[derive(Debug, Clone)]
+struct Stats {
+    total: u64,
+    kept: u64,
+    counts: Option<Counts>,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let percentage = match self.total {
+            0 => 0.,
+            total => self.kept as f64 * 100. / total as f64,
+        };
+
+        write!(
+            f,
+            "kept {}/{} frames ({percentage:.1}%)",
+            self.kept, self.total
+        )?;
+        if let Some(counts) = &self.counts {
+            let (count_in, count_out) = counts.total();
+            write!(f, ", {count_in} in, {count_out} out")?;
+        }
+
+        Ok(())
+    }
+}
+
 fn main() -> io::Result<()> {
     // Parse CLI arguments.
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // Run subcommand.
+    if let Some(Command::Probe(probe_args)) = args.command.take() {
+        return probe(probe_args, args.no_color);
+    }
+
     // Parse config file and override options with CLI arguments.
     let config = match Config::parse() {
         Ok(config) => config,
@@ -56,59 +112,372 @@ fn main() -> io::Result<()> {
     }
     .override_with_args(args);
 
-    // Format video file path as <config.directory/date&time>.
-    let filename = Local::now()
-        .format(
-            config
-                .directory
-                // Output video file name (derived by file format) + extension.
-                .join(Path::new(&config.format).with_extension("mkv"))
-                // Convert Path object to string.
-                .to_str()
-                .unwrap(),
+    // Check the input options and that the output video codec can be stored in the output video
+    // container.
+    if let Err(e) = config
+        .validate()
+        .and_then(|_| config.container.check(config.codec))
+    {
+        Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+        process::exit(1);
+    }
+
+    // Check GStreamer pipelines can be used.
+    if (config.input_pipeline.is_some() || config.output_pipeline.is_some())
+        && !gstreamer_available()
+    {
+        Colorizer::new(
+            MsgType::Error,
+            config.no_color,
+            "error",
+            ErrorKind::GStreamerUnavailable,
+        )
+        .print()?;
+        process::exit(1);
+    }
+
+    // Register signal hook for SIGINT events: in this case error is unrecoverable, so report it
+    // to the user & exit process with code error code.
+    let term = Arc::new(AtomicBool::new(false));
+    if let Err(e) = register(SIGINT, Arc::clone(&term)) {
+        Colorizer::new(
+            MsgType::Error,
+            config.no_color,
+            "fatal error",
+            format!("unable to register SIGINT hook '{e}'"),
+        )
+        .print()?;
+        process::exit(1);
+    };
+
+    match &config.video {
+        // Batch input: process each video file in turn.
+        Some(video) if input::is_batch(video) => {
+            if config.video_start.is_some() {
+                Colorizer::new(
+                    MsgType::Error,
+                    config.no_color,
+                    "error",
+                    ErrorKind::BatchVideoStart,
+                )
+                .print()?;
+                process::exit(1);
+            }
+            let inputs = match input::resolve(video) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
+                    process::exit(1);
+                }
+            };
+            batch(&config, inputs, &term)?;
+        }
+        // Single video file or live camera input.
+        video => {
+            let stats = match process(&config, video.as_deref(), false, &term)? {
+                Some(stats) => stats,
+                None => process::exit(1),
+            };
+            if !config.quiet && (video.is_some() || stats.counts.is_some()) {
+                Colorizer::new(MsgType::Info, config.no_color, "\n==> Summary", &stats).print()?;
+                // Per-hour boundary crossing counts.
+                for (hour, count_in, count_out) in stats.counts.iter().flat_map(Counts::hours) {
+                    Colorizer::new(
+                        MsgType::Info,
+                        config.no_color,
+                        hour.format("    %Y-%m-%d %H:00"),
+                        format!("{count_in} in, {count_out} out"),
+                    )
+                    .print()?;
+                }
+            }
+        }
+    }
+
+    // Gracefully terminated execution.
+    if !config.quiet {
+        Colorizer::new(MsgType::Info, config.no_color, "\nbombuscv", "done!").print()?;
+    }
+
+    Ok(())
+}
+
+/// Run `probe` subcommand: list the capture cameras and the capture modes they deliver.
+fn probe(args: ProbeArgs, no_color: bool) -> io::Result<()> {
+    let indexes = match args.index {
+        Some(index) => vec![index.into()],
+        None => probe::devices(),
+    };
+
+    let mut devices = Vec::new();
+    for index in indexes {
+        match probe::probe(index) {
+            Ok(device) => devices.push(device),
+            // Only report errors for explicitly requested cameras (some /dev/video nodes are
+            // metadata nodes of the same camera).
+            Err(e) if args.index.is_some() => {
+                Colorizer::new(MsgType::Error, no_color, "error", e).print()?;
+                process::exit(1);
+            }
+            Err(_) => {}
+        }
+    }
+
+    match args.json {
+        true => println!("{}", probe::json(&devices)),
+        false => {
+            if devices.is_empty() {
+                Colorizer::new(MsgType::Warn, no_color, "warning", "no camera found").print()?;
+            } else {
+                print!("{}", probe::table(&devices));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Process batch `inputs` (`config.jobs` video files in parallel) and print the summary of kept
+/// frames for each of them.
+fn batch(config: &Config, inputs: Vec<PathBuf>, term: &Arc<AtomicBool>) -> io::Result<()> {
+    let jobs = match config.jobs {
+        0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    }
+    .min(inputs.len());
+
+    if !config.quiet {
+        Colorizer::new(
+            MsgType::Info,
+            config.no_color,
+            "==> Batch",
+            format!("{} video files, {jobs} in parallel", inputs.len()),
         )
-        .to_string();
+        .print()?;
+    }
+
+    // Shared queue of the inputs to be processed & per-input statistics.
+    let queue = Arc::new(Mutex::new(inputs.clone().into_iter().enumerate()));
+    let results = Arc::new(Mutex::new(vec![None; inputs.len()]));
+
+    let handles: Vec<_> = (0..jobs)
+        .map(|_| {
+            let config = config.clone();
+            let term = Arc::clone(term);
+            let queue = Arc::clone(&queue);
+            let results = Arc::clone(&results);
+
+            thread::spawn(move || -> io::Result<()> {
+                // Stop picking inputs on SIGINT.
+                while !term.load(Ordering::Relaxed) {
+                    let (index, video) = match queue.lock().expect("poisoned input queue").next() {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let stats = process(&config, Some(&video), true, &term)?;
+                    results.lock().expect("poisoned batch results")[index] = stats;
+                }
+
+                Ok(())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().expect("cannot join batch thread")?;
+    }
+
+    // Print summary.
+    if !config.quiet {
+        Colorizer::new(MsgType::Info, config.no_color, "\n==> Summary", "").print()?;
+        let results = results.lock().expect("poisoned batch results");
+        for (video, stats) in inputs.iter().zip(results.iter()) {
+            let (msg_type, body) = match stats {
+                Some(stats) => (MsgType::Info, stats.to_string()),
+                None => (MsgType::Warn, String::from("not processed")),
+            };
+            Colorizer::new(msg_type, config.no_color, video.display(), body).print()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Process `video` input (live camera or network stream input if `None`): set up frame grabber,
+/// motion detector & video output and run `bombuscv`. Return `None` if processing can't start
+/// (the error is reported to the user).
+///
+/// Batch inputs are named after the input video file, rather than the date&time.
+fn process(
+    config: &Config,
+    video: Option<&Path>,
+    batch: bool,
+    term: &Arc<AtomicBool>,
+) -> io::Result<Option<Stats>> {
+    // Report the error to the user & skip processing.
+    let fail = |e| -> io::Result<Option<Stats>> {
+        let prefix = match video {
+            Some(video) if batch => format!("error [{}]", video.display()),
+            _ => String::from("error"),
+        };
+        Colorizer::new(MsgType::Error, config.no_color, prefix, e).print()?;
+        Ok(None)
+    };
 
     // Instance of the frame grabber.
-    let grabber = match &config.video {
+    let grabber = match (video, &config.images, &config.url) {
+        // VideoCapture is GStreamer pipeline.
+        (None, _, _) if config.input_pipeline.is_some() => {
+            Grabber::from_pipeline(config.input_pipeline.as_deref().unwrap_or_default())
+        }
         // VideoCapture is video file.
-        Some(video) => Grabber::from_file(video),
+        (Some(video), _, _) => Grabber::from_file(video, config.video_start, &config.format),
+        // Input is image sequence.
+        (None, Some(images), _) => Grabber::from_images(images, config.framerate.into()),
+        // VideoCapture is network stream.
+        (None, None, Some(url)) => Grabber::from_url(
+            url,
+            Duration::from_secs_f64(config.stream_timeout.max(0.)),
+            config.framerate.into(),
+        ),
         // VideoCapture is live camera.
-        None => Grabber::new(
+        (None, None, None) => Grabber::new(
             config.index.into(),
             config.height.into(),
             config.width.into(),
             config.framerate.into(),
         ),
     };
-    let grabber = match grabber {
+    let mut grabber = match grabber {
         Ok(grabber) => grabber,
-        Err(e) => {
-            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
-            process::exit(1);
-        }
+        Err(e) => return fail(e),
     };
+    grabber.set_reconnect(config.reconnect_after, config.reconnect_backoff);
+    if let (Some(video), true) = (video, grabber.is_start_fallback()) {
+        Colorizer::new(
+            MsgType::Warn,
+            config.no_color,
+            "warning",
+            format!(
+                "unable to determine '{}' recording start, timestamping frames from now",
+                video.display()
+            ),
+        )
+        .print()?;
+    }
+    let controls = grabber.set_controls(config.camera);
+
+    // Output video specification.
+    let mut output = Output {
+        directory: config.directory.clone(),
+        format: match video {
+            Some(video) if batch => input::batch_format(video),
+            _ => config.format.clone(),
+        },
+        container: config.container,
+        codec: config.codec,
+        fps: grabber.get_fps(),
+        size: grabber.get_size(),
+        overlay: config.overlay,
+        overlay_border: config.overlay_border,
+        clips: config.clips,
+        timestamps: config.timestamps,
+        pipeline: config.output_pipeline.clone(),
+        images: config.image_output,
+        snapshots: config.snapshots,
+        snapshot_boxes: config.snapshot_boxes,
+        contact_sheet: config.contact_sheet,
+        debug_overlay: config.debug_overlay,
+        debug_video: config.debug_video,
+        track_overlay: config.track_overlay,
+    };
+
+    // Fall back to another codec if the OpenCV build can't write the requested one.
+    match output.available_codec() {
+        Ok(codec) => {
+            if codec != output.codec {
+                Colorizer::new(
+                    MsgType::Warn,
+                    config.no_color,
+                    "warning",
+                    format!(
+                        "{} codec unavailable, falling back to {codec}",
+                        output.codec
+                    ),
+                )
+                .print()?;
+                output.codec = codec;
+            }
+        }
+        Err(e) => return fail(e),
+    }
+
+    // Session start date&time: recording start date&time for video file input.
+    let start = grabber.get_start().unwrap_or_else(Local::now);
+
+    // Format video file path as <config.directory/date&time>.
+    let filename = output.path(&start, None);
 
     // Print info.
     if !config.quiet {
         let mut colorizer = Colorizer::empty(MsgType::Info, config.no_color);
 
-        let input = if let Some(video) = &config.video {
-            video.display().to_string()
-        } else {
-            format!("/dev/video{}", &config.index)
+        let input = match (video, &config.images, &config.url) {
+            (None, _, _) if config.input_pipeline.is_some() => {
+                format!(
+                    "GStreamer pipeline '{}'",
+                    config.input_pipeline.as_deref().unwrap_or_default()
+                )
+            }
+            (Some(video), _, _) => video.display().to_string(),
+            (None, Some(images), _) => format!("image sequence '{}'", images.display()),
+            (None, None, Some(url)) => url.to_string(),
+            (None, None, None) => format!("/dev/video{}", &config.index),
         };
 
-        let messages = vec![
-            ("==> Input", input),
-            ("==> Framerate", grabber.get_fps().to_string()),
-            (
-                "==> Frame size",
-                format!("{}x{}", grabber.get_width(), grabber.get_height()),
-            ),
-            ("==> Printing overlay", format!("{}", config.overlay)),
-            ("==> Output video file", filename.clone()),
-        ];
+        let mut messages = vec![("==> Input", input)];
+        // Batch inputs share the same settings: only print the input.
+        if !batch {
+            messages.extend([
+                ("==> Framerate", grabber.get_fps().to_string()),
+                (
+                    "==> Frame size",
+                    format!("{}x{}", grabber.get_width(), grabber.get_height()),
+                ),
+                ("==> Printing overlay", format!("{}", config.overlay)),
+                (
+                    "==> Output video format",
+                    match output.images {
+                        Some(format) => format!("{} images", format.extension()),
+                        None => format!("{} ({})", output.container, output.codec),
+                    },
+                ),
+                (
+                    "==> Pre-roll frames",
+                    config.pre_roll.frames(grabber.get_fps()).to_string(),
+                ),
+                (
+                    "==> Post-roll frames",
+                    config.post_roll.frames(grabber.get_fps()).to_string(),
+                ),
+                match output.clips {
+                    true => (
+                        "==> Output video clips",
+                        output
+                            .directory
+                            .join(format!("{}_<event>.{}", output.format, output.container))
+                            .display()
+                            .to_string(),
+                    ),
+                    false => ("==> Output video file", filename),
+                },
+            ]);
+        }
+
+        // Camera controls, as applied by the driver.
+        for control in controls {
+            messages.push(("==> Camera control", control.to_string()));
+        }
 
         for msg in messages {
             colorizer.update(msg.0, msg.1);
@@ -116,46 +485,173 @@ fn main() -> io::Result<()> {
         }
     }
 
-    // Instance of the motion detector.
-    let detector = MotionDetector::new();
+    // Check the motion detector can be built: OpenCV background subtractors can't be sent across
+    // threads, so the motion detector is built on the motion detection thread.
+    if let Err(e) = MotionDetector::new(config.detector.clone()) {
+        return fail(e);
+    }
 
-    // Instance of the frame writer.
-    let writer = match Writer::new(
-        &filename,
-        Codec::XVID,
-        grabber.get_fps(),
-        grabber.get_size(),
-        config.overlay,
-        config.overlay_border,
-    ) {
-        Ok(writer) => writer,
-        Err(e) => {
-            Colorizer::new(MsgType::Error, config.no_color, "error", e).print()?;
-            process::exit(1);
+    // Instance of the contour classifier, rejecting non-bee motion.
+    let mut dnn_classifier = None;
+    if let Some(params) = &config.classifier {
+        match Classifier::new(params.clone()) {
+            Ok(classifier) => dnn_classifier = Some(classifier),
+            Err(e) => return fail(e),
         }
+    }
+
+    // Instance of the contour tracker, counting boundary crossings & writing trajectories next
+    // to the output videos.
+    let mut tracker = None;
+    if config.tracker.enabled() || config.track_overlay {
+        let mut contour_tracker = match Tracker::new(config.tracker.clone()) {
+            Ok(tracker) => tracker,
+            Err(e) => return fail(e),
+        };
+        if let Some(format) = config.tracker.trajectories {
+            let path = output.path_with_extension(&start, None, format.tracks_extension());
+            match Trajectories::create(Path::new(&path), format) {
+                Ok(trajectories) => contour_tracker.set_trajectories(trajectories),
+                Err(e) => return fail(e),
+            }
+        }
+        tracker = Some(contour_tracker);
+    }
+
+    // Instance of the colour tag classifier, identifying marked individuals.
+    let mut tag_classifier = None;
+    if !config.tags.is_empty() {
+        match TagClassifier::new(config.tags.clone()) {
+            Ok(classifier) => tag_classifier = Some(classifier),
+            Err(e) => return fail(e),
+        }
+    }
+
+    // Check the fiducial marker reader can be built (on the motion detection thread too, as
+    // OpenCV marker dictionaries can't be sent across threads): fall back to no marker decoding if
+    // bombuscv was built without the `aruco` feature.
+    let mut marker_params = None;
+    if let Some(params) = &config.markers {
+        match MarkerReader::new(params) {
+            Ok(_) => marker_params = Some(params.clone()),
+            Err(ErrorKind::ArucoUnavailable) => {
+                Colorizer::new(
+                    MsgType::Warn,
+                    config.no_color,
+                    "warning",
+                    ErrorKind::ArucoUnavailable,
+                )
+                .print()?;
+            }
+            Err(e) => return fail(e),
+        }
+    }
+
+    // Instance of the event log, next to the output videos.
+    let event_log = match config.event_log {
+        Some(format) => {
+            let path = output.path_with_extension(&start, None, format.extension());
+            match EventLog::create(Path::new(&path), format) {
+                Ok(event_log) => Some(event_log),
+                Err(e) => return fail(e),
+            }
+        }
+        None => None,
     };
 
-    // Save memory dropping `filename`.
-    drop(filename);
+    // Per-hour boundary crossing counts file, next to the event log.
+    let counts_log = config.event_log.map(|format| {
+        let path = output.path_with_extension(&start, None, format.counts_extension());
+        (PathBuf::from(path), format)
+    });
+
+    // Instance of the marker log, next to the event log.
+    let marker_log = match config.event_log {
+        Some(format) if marker_params.is_some() => {
+            let path = output.path_with_extension(&start, None, format.markers_extension());
+            match MarkerLog::create(Path::new(&path), format) {
+                Ok(marker_log) => Some(marker_log),
+                Err(e) => return fail(e),
+            }
+        }
+        _ => None,
+    };
+
+    // Instance of the output recorder.
+    let mut recorder = match Recorder::new(output, &start, event_log) {
+        Ok(recorder) => recorder,
+        Err(e) => return fail(e),
+    };
+    if let Some(marker_log) = marker_log {
+        recorder.set_marker_log(marker_log);
+    }
+
+    // Instance of the event state machine.
+    let events = EventStateMachine::new(
+        config.min_motion_frames,
+        config.post_roll.frames(grabber.get_fps()) as u32,
+    );
+
+    // Instance of the pre-roll buffer: it also retains the frames triggering an event, until the
+    // minimum consecutive motion frames are reached.
+    let pre_roll = PreRoll::new(
+        config.pre_roll.frames(grabber.get_fps()) + config.min_motion_frames.max(1) as usize - 1,
+    );
+
+    // Motion detector builder, run on the motion detection thread.
+    let detector_params = config.detector.clone();
+    let keep_mask = config.debug_video;
+    let detector = move || -> Result<MotionDetector, ErrorKind> {
+        let mut detector = MotionDetector::new(detector_params)?;
+        detector.keep_mask(keep_mask);
+        if let Some(dnn_classifier) = dnn_classifier {
+            detector.set_dnn_classifier(dnn_classifier);
+        }
+        if let Some(tracker) = tracker {
+            detector.set_tracker(tracker);
+        }
+        if let Some(tag_classifier) = tag_classifier {
+            detector.set_tag_classifier(tag_classifier);
+        }
+        if let Some(params) = marker_params {
+            detector.set_marker_reader(MarkerReader::new(&params)?);
+        }
+
+        Ok(detector)
+    };
 
     // Run the program.
-    run(grabber, detector, writer, config.no_color)?;
+    let stats = run(
+        grabber,
+        detector,
+        events,
+        pre_roll,
+        recorder,
+        Arc::clone(term),
+        config.no_color,
+    )?;
 
-    // Gracefully terminated execution.
-    if !config.quiet {
-        Colorizer::new(MsgType::Info, config.no_color, "\nbombuscv", "done!").print()?;
+    if let (Some(counts), Some((path, format))) = (&stats.counts, counts_log) {
+        if let Err(e) = counts.write(&path, format) {
+            Colorizer::new(MsgType::Warn, config.no_color, "warning", e).print()?;
+        }
     }
 
-    Ok(())
+    Ok(Some(stats))
 }
 
-/// Run `bombuscv`: spawn & join frame grabber, detector and writer threads.
+/// Run `bombuscv`: spawn & join frame grabber, detector and writer threads, until the input is
+/// exhausted or `term` is set (SIGINT). The motion detector is built by `detector` on the
+/// detector thread. Return the processed frames statistics.
 fn run(
     mut grabber: Grabber,
-    mut detector: MotionDetector,
-    mut writer: Writer,
+    detector: impl FnOnce() -> Result<MotionDetector, ErrorKind> + Send + 'static,
+    mut events: EventStateMachine,
+    mut pre_roll: PreRoll<Detection>,
+    mut recorder: Recorder,
+    term: Arc<AtomicBool>,
     no_color: bool,
-) -> io::Result<()> {
+) -> io::Result<Stats> {
     // Create channels for message passing between threads.
     // NOTE: using mpsc::sync_channel (blocking) to avoid channel size
     // growing indefinitely, resulting in infinite memory usage.
@@ -165,26 +661,15 @@ fn run(
     // Spawn frame grabber thread:
     // this thread captures frames and passes them to the motion detecting thread.
     let grabber_handle = thread::spawn(move || -> io::Result<()> {
-        let term = Arc::new(AtomicBool::new(false));
-        // Register signal hook for SIGINT events: in this case error is unrecoverable, so report
-        // it to the user & exit process with code error code.
-        if let Err(e) = register(SIGINT, Arc::clone(&term)) {
-            Colorizer::new(
-                MsgType::Error,
-                no_color,
-                "fatal error",
-                format!("unable to register SIGINT hook '{e}'"),
-            )
-            .print()?;
-            process::exit(1);
-        };
-
         // Start grabber loop: loop guard is 'received SIGINT'.
         while !term.load(Ordering::Relaxed) {
             let frame = match grabber.grab() {
                 Ok(frame) => frame,
                 Err(e) => {
                     Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+                    if grabber.needs_reconnect() {
+                        reconnect(&mut grabber, &term, no_color)?;
+                    }
                     continue;
                 }
             };
@@ -201,55 +686,141 @@ fn run(
     // Spawn motion detection thread:
     // this thread receives frames from the grabber thread, processes it and if motion is detected,
     // passes the frame to the frame writing thread.
-    let detector_handle = thread::spawn(move || -> io::Result<()> {
+    let detector_handle = thread::spawn(move || -> io::Result<(u64, Option<Counts>)> {
+        let mut detector = match detector() {
+            Ok(detector) => detector,
+            Err(e) => {
+                Colorizer::new(MsgType::Error, no_color, "error", e).print()?;
+                return Ok((0, None));
+            }
+        };
+
+        // Number of processed frames.
+        let mut total = 0;
+
+        // Send packet to the frame writer thread.
+        let send = |packet: Packet| -> io::Result<()> {
+            if proc_tx.send(packet).is_err() {
+                Colorizer::new(
+                    MsgType::Warn,
+                    no_color,
+                    "warning",
+                    "unable to send processed frame to video output",
+                )
+                .print()?;
+            }
+
+            Ok(())
+        };
+
         // Loop over received frames from the frame grabber.
         for frame in raw_rx {
             match detector.detect_motion(frame) {
                 // Valid frame is received.
-                Ok(val) => {
-                    // Motion has been detected: send frame to the video writer.
-                    if let Some(frame) = val {
-                        if proc_tx.send(frame).is_err() {
-                            Colorizer::new(
-                                MsgType::Warn,
-                                no_color,
-                                "warning",
-                                "unable to send processed frame to video output",
-                            )
-                            .print()?;
-                        };
+                Ok(detection) => {
+                    total += 1;
+                    if let Err(e) = detector.log_tracks(&detection) {
+                        Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+                    }
+                    match events.update(detection.is_motion()) {
+                        // Event started: send buffered frames (oldest first) and then the current
+                        // frame to the video writer.
+                        Transition::Start => {
+                            send(Packet::Start)?;
+                            for buffered in pre_roll.drain() {
+                                send(Packet::Frame(buffered))?;
+                            }
+                            send(Packet::Frame(detection))?;
+                        }
+                        // Event in progress: send the current frame to the video writer.
+                        _ if events.is_recording() => send(Packet::Frame(detection))?,
+                        // Event ended: keep the frame in the pre-roll buffer.
+                        Transition::End => {
+                            send(Packet::End)?;
+                            pre_roll.push(detection);
+                        }
+                        // No event in progress: keep the frame in the pre-roll buffer.
+                        Transition::None => pre_roll.push(detection),
                     }
                 }
                 // Last captured frame was an empty frame: no more input is provided, interrupt the
                 // thread (break the loop).
-                Err(_) => break,
+                Err(ErrorKind::EmptyFrame) => break,
+                // Analysis stage failure (colour tags, markers, classifier): skip the frame.
+                Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?,
             }
         }
 
-        Ok(())
+        Ok((total, detector.counts().cloned()))
     });
 
     // Spawn frame writer thread:
     // this thread receives the processed frames by the motion detecting thread and writes them in
     // the output video output.
-    let writer_handle = thread::spawn(move || -> io::Result<()> {
-        // Loop over received frames from the motion detector.
-        for frame in proc_rx {
-            // Write processed frames (motion detected) to the video output.
-            if let Err(e) = writer.write(frame) {
-                Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+    let writer_handle = thread::spawn(move || -> io::Result<u64> {
+        // Loop over received packets from the motion detector.
+        for packet in proc_rx {
+            let result = match packet {
+                Packet::Start => {
+                    recorder.start();
+                    Ok(())
+                }
+                // Write processed frames (motion detected) to the video output.
+                Packet::Frame(detection) => recorder.write(detection),
+                Packet::End => recorder.end(),
             };
+
+            if let Err(e) = result {
+                Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+            }
         }
 
-        Ok(())
+        // End the session: execution may have been interrupted while an event was in progress.
+        if let Err(e) = recorder.finish() {
+            Colorizer::new(MsgType::Warn, no_color, "warning", e).print()?;
+        }
+
+        Ok(recorder.written())
     });
 
     // Join all threads.
     grabber_handle.join().expect("cannot join grabber thread")?;
-    detector_handle
+    let (total, counts) = detector_handle
         .join()
         .expect("cannot join detector thread")?;
-    writer_handle.join().expect("cannot join writer thread")?;
+    let kept = writer_handle.join().expect("cannot join writer thread")?;
 
-    Ok(())
+    Ok(Stats {
+        total,
+        kept,
+        counts,
+    })
+}
+
+/// Reconnect the live input (camera or network stream), after waiting the reconnection delay
+/// (interrupted by SIGINT). The pipeline carries on writing to the current video output once the
+/// input is back.
+fn reconnect(grabber: &mut Grabber, term: &AtomicBool, no_color: bool) -> io::Result<()> {
+    let delay = grabber.reconnect_delay();
+    Colorizer::new(
+        MsgType::Warn,
+        no_color,
+        "warning",
+        format!("reconnecting video input in {:.1}s", delay.as_secs_f64()),
+    )
+    .print()?;
+
+    // Sleep in short steps, so that SIGINT is handled promptly.
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        if term.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        thread::sleep(RECONNECT_POLL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+
+    match grabber.reconnect() {
+        Ok(()) => Colorizer::new(MsgType::Info, no_color, "==> Input", "reconnected").print(),
+        Err(e) => Colorizer::new(MsgType::Warn, no_color, "warning", e).print(),
+    }
 }
@@ -0,0 +1,354 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+//! Fiducial marker (ArUco/AprilTag) decoding around the motion contours, through OpenCV's contrib
+//! `aruco` module: only available if built with the `aruco` feature.
+
+use crate::{
+    error::ErrorKind,
+    eventlog::{json_string, LogFormat},
+    Blob,
+};
+use chrono::{DateTime, Local, SecondsFormat};
+use opencv::prelude::Mat;
+use serde::Deserialize;
+use std::{fs::File, io::Write, path::Path};
+
+#[cfg(feature = "aruco")]
+use opencv::{
+    aruco::{
+        detect_markers, get_predefined_dictionary, DetectorParameters, Dictionary,
+        PREDEFINED_DICTIONARY_NAME,
+    },
+    core::{Point2f, Ptr, Rect, Vector},
+    prelude::MatTraitConst,
+};
+#[cfg(feature = "aruco")]
+use std::fmt;
+
+/// Default value for the margin around the contours searched for markers.
+fn default_margin() -> f64 {
+    0.5
+}
+
+/// Predefined marker dictionary.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerDictionary {
+    #[serde(rename = "4x4_50")]
+    Dict4X4_50,
+    #[serde(rename = "4x4_100")]
+    Dict4X4_100,
+    #[serde(rename = "4x4_250")]
+    Dict4X4_250,
+    #[serde(rename = "4x4_1000")]
+    Dict4X4_1000,
+    #[serde(rename = "5x5_50")]
+    Dict5X5_50,
+    #[serde(rename = "5x5_100")]
+    Dict5X5_100,
+    #[serde(rename = "5x5_250")]
+    Dict5X5_250,
+    #[serde(rename = "5x5_1000")]
+    Dict5X5_1000,
+    #[serde(rename = "6x6_50")]
+    Dict6X6_50,
+    #[serde(rename = "6x6_100")]
+    Dict6X6_100,
+    #[serde(rename = "6x6_250")]
+    Dict6X6_250,
+    #[serde(rename = "6x6_1000")]
+    Dict6X6_1000,
+    #[serde(rename = "7x7_50")]
+    Dict7X7_50,
+    #[serde(rename = "7x7_100")]
+    Dict7X7_100,
+    #[serde(rename = "7x7_250")]
+    Dict7X7_250,
+    #[serde(rename = "7x7_1000")]
+    Dict7X7_1000,
+    #[serde(rename = "aruco_original")]
+    ArucoOriginal,
+    #[serde(rename = "apriltag_16h5")]
+    AprilTag16h5,
+    #[serde(rename = "apriltag_25h9")]
+    AprilTag25h9,
+    #[serde(rename = "apriltag_36h10")]
+    AprilTag36h10,
+    #[serde(rename = "apriltag_36h11")]
+    AprilTag36h11,
+}
+
+impl Default for MarkerDictionary {
+    fn default() -> Self {
+        Self::Dict4X4_50
+    }
+}
+
+#[cfg(feature = "aruco")]
+impl MarkerDictionary {
+    /// Return the OpenCV predefined dictionary name.
+    fn name(&self) -> PREDEFINED_DICTIONARY_NAME {
+        use PREDEFINED_DICTIONARY_NAME::*;
+        match self {
+            Self::Dict4X4_50 => DICT_4X4_50,
+            Self::Dict4X4_100 => DICT_4X4_100,
+            Self::Dict4X4_250 => DICT_4X4_250,
+            Self::Dict4X4_1000 => DICT_4X4_1000,
+            Self::Dict5X5_50 => DICT_5X5_50,
+            Self::Dict5X5_100 => DICT_5X5_100,
+            Self::Dict5X5_250 => DICT_5X5_250,
+            Self::Dict5X5_1000 => DICT_5X5_1000,
+            Self::Dict6X6_50 => DICT_6X6_50,
+            Self::Dict6X6_100 => DICT_6X6_100,
+            Self::Dict6X6_250 => DICT_6X6_250,
+            Self::Dict6X6_1000 => DICT_6X6_1000,
+            Self::Dict7X7_50 => DICT_7X7_50,
+            Self::Dict7X7_100 => DICT_7X7_100,
+            Self::Dict7X7_250 => DICT_7X7_250,
+            Self::Dict7X7_1000 => DICT_7X7_1000,
+            Self::ArucoOriginal => DICT_ARUCO_ORIGINAL,
+            Self::AprilTag16h5 => DICT_APRILTAG_16h5,
+            Self::AprilTag25h9 => DICT_APRILTAG_25h9,
+            Self::AprilTag36h10 => DICT_APRILTAG_36h10,
+            Self::AprilTag36h11 => DICT_APRILTAG_36h11,
+        }
+    }
+}
+
+/// Fiducial marker decoding parameters.
+///
+/// # Fields
+/// * dictionary: predefined marker dictionary
+/// * margin: margin around the contour bounding rectangles searched for markers, as fraction of
+///   the rectangle size
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MarkerParams {
+    #[serde(default)]
+    pub dictionary: MarkerDictionary,
+    #[serde(default = "default_margin")]
+    pub margin: f64,
+}
+
+impl Default for MarkerParams {
+    fn default() -> Self {
+        Self {
+            dictionary: MarkerDictionary::default(),
+            margin: default_margin(),
+        }
+    }
+}
+
+impl MarkerParams {
+    /// Check marker decoding parameters consistency.
+    pub fn validate(&self) -> Result<(), ErrorKind> {
+        if !self.margin.is_finite() || self.margin < 0. {
+            return Err(ErrorKind::InvalidMarkerParams(format!(
+                "margin must be finite and non-negative, found {}",
+                self.margin
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Decoded fiducial marker.
+///
+/// # Fields
+/// * id: marker ID in the dictionary
+/// * x: marker center abscissa in original frame coordinates
+/// * y: marker center ordinate in original frame coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+    pub id: i32,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Fiducial marker reader: decodes the markers around the motion contours.
+///
+/// # Fields
+/// * margin: margin around the contour bounding rectangles, as fraction of the rectangle size
+/// * dictionary: OpenCV marker dictionary
+/// * parameters: OpenCV marker detection parameters
+#[cfg(feature = "aruco")]
+pub struct MarkerReader {
+    margin: f64,
+    dictionary: Ptr<Dictionary>,
+    parameters: Ptr<DetectorParameters>,
+}
+
+#[cfg(feature = "aruco")]
+impl fmt::Debug for MarkerReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MarkerReader")
+            .field("margin", &self.margin)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "aruco")]
+impl MarkerReader {
+    /// Create an instance of the MarkerReader, return Err if `params` are not valid.
+    pub fn new(params: &MarkerParams) -> Result<Self, ErrorKind> {
+        params.validate()?;
+
+        Ok(Self {
+            margin: params.margin,
+            dictionary: get_predefined_dictionary(params.dictionary.name())
+                .map_err(|_| ErrorKind::MarkerReaderErr)?,
+            parameters: DetectorParameters::create().map_err(|_| ErrorKind::MarkerReaderErr)?,
+        })
+    }
+
+    /// Return the markers decoded around the `blobs` in `frame`, each marker ID once.
+    pub fn read(&self, frame: &Mat, blobs: &[Blob]) -> Result<Vec<Marker>, ErrorKind> {
+        let bounds = Rect::new(0, 0, frame.cols(), frame.rows());
+        let mut markers: Vec<Marker> = Vec::new();
+
+        for blob in blobs {
+            let (dx, dy) = (
+                (f64::from(blob.rect.width) * self.margin).round() as i32,
+                (f64::from(blob.rect.height) * self.margin).round() as i32,
+            );
+            let region = Rect::new(
+                blob.rect.x - dx,
+                blob.rect.y - dy,
+                blob.rect.width + 2 * dx,
+                blob.rect.height + 2 * dy,
+            ) & bounds;
+            if region.area() == 0 {
+                continue;
+            }
+
+            let mut corners: Vector<Vector<Point2f>> = Vector::new();
+            let mut ids: Vector<i32> = Vector::new();
+            let mut rejected: Vector<Vector<Point2f>> = Vector::new();
+            Mat::roi(frame, region)
+                .and_then(|region| {
+                    detect_markers(
+                        &region,
+                        &self.dictionary,
+                        &mut corners,
+                        &mut ids,
+                        &self.parameters,
+                        &mut rejected,
+                    )
+                })
+                .map_err(|_| ErrorKind::MarkerDecodingErr)?;
+
+            for (id, corners) in ids.iter().zip(corners.iter()) {
+                // Overlapping regions may decode the same marker.
+                if markers.iter().any(|marker| marker.id == id) {
+                    continue;
+                }
+                // Marker center, from region to frame coordinates.
+                let n = corners.len() as f64;
+                let (x, y) = corners.iter().fold((0., 0.), |(x, y), corner| {
+                    (x + f64::from(corner.x) / n, y + f64::from(corner.y) / n)
+                });
+                markers.push(Marker {
+                    id,
+                    x: x + f64::from(region.x),
+                    y: y + f64::from(region.y),
+                });
+            }
+        }
+
+        Ok(markers)
+    }
+}
+
+/// Fiducial marker reader placeholder, built without the `aruco` feature: marker decoding is
+/// unavailable.
+#[cfg(not(feature = "aruco"))]
+#[derive(Debug)]
+pub struct MarkerReader {
+    _private: (),
+}
+
+#[cfg(not(feature = "aruco"))]
+impl MarkerReader {
+    /// Return Err: marker decoding requires the `aruco` feature (and OpenCV contrib modules).
+    pub fn new(params: &MarkerParams) -> Result<Self, ErrorKind> {
+        params.validate()?;
+        Err(ErrorKind::ArucoUnavailable)
+    }
+
+    /// Return no markers.
+    pub fn read(&self, _frame: &Mat, _blobs: &[Blob]) -> Result<Vec<Marker>, ErrorKind> {
+        Ok(Vec::new())
+    }
+}
+
+/// Marker log CSV header.
+const MARKERS_CSV_HEADER: &str = "event,id,datetime,frame,x,y";
+
+/// Marker log: one record per decoded marker per event frame.
+///
+/// # Fields
+/// * file: marker log file
+/// * format: marker log file format
+#[derive(Debug)]
+pub struct MarkerLog {
+    file: File,
+    format: LogFormat,
+}
+
+impl MarkerLog {
+    /// Create the marker log file at `path` (writing the header, where relevant).
+    pub fn create(path: &Path, format: LogFormat) -> Result<Self, ErrorKind> {
+        let mut file = File::create(path).map_err(|_| ErrorKind::InvalidMarkerLog)?;
+        if format == LogFormat::Csv {
+            writeln!(file, "{MARKERS_CSV_HEADER}").map_err(|_| ErrorKind::InvalidMarkerLog)?;
+        }
+
+        Ok(Self { file, format })
+    }
+
+    /// Append the `markers` decoded in an event frame.
+    ///
+    /// # Parameters
+    /// * event: event counter
+    /// * frame: frame index in the output holding the event
+    /// * datetime: frame date&time
+    /// * markers: markers decoded in the frame
+    pub fn write(
+        &mut self,
+        event: u32,
+        frame: u64,
+        datetime: &DateTime<Local>,
+        markers: &[Marker],
+    ) -> Result<(), ErrorKind> {
+        let datetime = datetime.to_rfc3339_opts(SecondsFormat::Millis, false);
+
+        let mut content = String::new();
+        for Marker { id, x, y } in markers {
+            content.push_str(&match self.format {
+                LogFormat::Jsonl => format!(
+                    "{{\"event\":{event},\"id\":{id},\"datetime\":{},\"frame\":{frame},\
+                     \"x\":{x:.1},\"y\":{y:.1}}}\n",
+                    json_string(&datetime)
+                ),
+                LogFormat::Csv => format!("{event},{id},{datetime},{frame},{x:.1},{y:.1}\n"),
+            });
+        }
+
+        self.file
+            .write_all(content.as_bytes())
+            .map_err(|_| ErrorKind::MarkerLogWriteErr)
+    }
+}
@@ -0,0 +1,437 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    error::ErrorKind,
+    eventlog::{EventLog, EventRecord},
+    images::ImageFormat,
+    markers::MarkerLog,
+    snapshot::{ContactSheet, Snapshot},
+    timestamps::{TimestampFormat, Timestamps},
+    Codec, Container, Detection, Writer,
+};
+use chrono::{DateTime, Local};
+use opencv::core::Size;
+use std::{
+    env, fs, iter, mem,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of codec probes, naming the probe files.
+static PROBES: AtomicUsize = AtomicUsize::new(0);
+
+/// Video output specification, used to open video writers: either one for the whole session or
+/// one per motion event (clip).
+///
+/// # Fields
+/// * directory: output video directory
+/// * format: output video filename format (chrono strftime specifiers)
+/// * container: output video container
+/// * codec: output video codec
+/// * fps: output video framerate
+/// * size: output video frame size
+/// * overlay: date&time video overlay
+/// * overlay_border: date&time video overlay border
+/// * clips: one output video file per motion event
+/// * timestamps: frame timestamp sidecar file format (one sidecar per output video file)
+/// * pipeline: GStreamer pipeline sink (`{path}` is replaced by the output video file path)
+/// * images: image sequence output format (one directory of images instead of a video file)
+/// * snapshots: save an image of the event frame with the largest motion area next to the output
+/// * snapshot_boxes: draw the motion contours bounding rectangles on snapshots
+/// * contact_sheet: save a contact sheet tiling the session event snapshots
+/// * debug_overlay: motion debug overlay (contour bounding rectangles, motion area & frame index)
+/// * debug_video: write a debug video (frame & motion mask side by side) next to each output
+/// * track_overlay: track overlay (track bounding rectangles & IDs)
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub directory: PathBuf,
+    pub format: String,
+    pub container: Container,
+    pub codec: Codec,
+    pub fps: f64,
+    pub size: Size,
+    pub overlay: bool,
+    pub overlay_border: u8,
+    pub clips: bool,
+    pub timestamps: Option<TimestampFormat>,
+    pub pipeline: Option<String>,
+    pub images: Option<ImageFormat>,
+    pub snapshots: bool,
+    pub snapshot_boxes: bool,
+    pub contact_sheet: bool,
+    pub debug_overlay: bool,
+    pub debug_video: bool,
+    pub track_overlay: bool,
+}
+
+impl Output {
+    /// Return the output video file path as `<directory>/<datetime formatted>.<extension>`, or
+    /// as `<directory>/<datetime formatted>_<event>.<extension>` if `event` counter is given.
+    /// Image sequence outputs are directories, without extension.
+    pub fn path(&self, datetime: &DateTime<Local>, event: Option<u32>) -> String {
+        match self.images {
+            Some(_) => {
+                let path = self.path_with_extension(datetime, event, "");
+                path.trim_end_matches('.').to_string()
+            }
+            None => self.path_with_extension(datetime, event, self.container.extension()),
+        }
+    }
+
+    /// Same as `path`, with custom file `extension` (i.e. for sidecar files).
+    pub fn path_with_extension(
+        &self,
+        datetime: &DateTime<Local>,
+        event: Option<u32>,
+        extension: &str,
+    ) -> String {
+        let mut filename = datetime.format(&self.format).to_string();
+        if let Some(event) = event {
+            filename = format!("{filename}_{event:04}");
+        }
+
+        self.directory
+            .join(format!("{filename}.{extension}"))
+            .to_str()
+            .expect("invalid UTF-8 output path")
+            .to_string()
+    }
+
+    /// Open a video writer to `path`, through the GStreamer pipeline sink if given, or an image
+    /// sequence writer to the `path` directory.
+    pub fn open(&self, path: &str) -> Result<Writer, ErrorKind> {
+        let mut writer = if let Some(format) = self.images {
+            Writer::images(path, format, self.overlay, self.overlay_border)?
+        } else if let Some(pipeline) = &self.pipeline {
+            Writer::from_pipeline(
+                &pipeline.replace("{path}", path),
+                path,
+                self.fps,
+                self.size,
+                self.overlay,
+                self.overlay_border,
+            )?
+        } else {
+            Writer::new(
+                path,
+                self.codec,
+                self.fps,
+                self.size,
+                self.overlay,
+                self.overlay_border,
+            )?
+        };
+        writer.set_debug_overlay(self.debug_overlay);
+        writer.set_track_overlay(self.track_overlay);
+
+        Ok(writer)
+    }
+
+    /// Open the debug video writer (frame & motion mask side by side, twice as wide as the
+    /// output) named after `datetime` and `event` counter, as `<name>.debug.<extension>`.
+    pub fn open_debug(
+        &self,
+        datetime: &DateTime<Local>,
+        event: Option<u32>,
+    ) -> Result<Writer, ErrorKind> {
+        let extension = format!("debug.{}", self.container.extension());
+        Writer::new(
+            &self.path_with_extension(datetime, event, &extension),
+            self.codec,
+            self.fps,
+            Size::new(self.size.width * 2, self.size.height),
+            self.overlay,
+            self.overlay_border,
+        )
+    }
+
+    /// Return the first codec (starting from the requested one) the OpenCV build is able to write
+    /// to the output container, probing a file in the system temporary directory. Encoding is up
+    /// to the GStreamer pipeline sink, if given, and image sequence outputs need none: the codec
+    /// is still probed for the debug video, which is always written by OpenCV.
+    pub fn available_codec(&self) -> Result<Codec, ErrorKind> {
+        if (self.pipeline.is_some() || self.images.is_some()) && !self.debug_video {
+            return Ok(self.codec);
+        }
+
+        // Concurrent probes (batch jobs) get their own file.
+        let probe = env::temp_dir().join(format!(
+            "bombuscv-probe-{}-{}.{}",
+            process::id(),
+            PROBES.fetch_add(1, Ordering::Relaxed),
+            self.container.extension()
+        ));
+        let probe = probe.to_str().expect("invalid UTF-8 output path");
+
+        let fallbacks = Codec::ALL
+            .into_iter()
+            .filter(|codec| *codec != self.codec && self.container.supports(*codec));
+        let codec = iter::once(self.codec).chain(fallbacks).find(|codec| {
+            // The probe writer is released as soon as it's dropped.
+            Writer::new(probe, *codec, self.fps, self.size, false, 0).is_ok()
+        });
+        // The probe file may not even have been created.
+        let _ = fs::remove_file(probe);
+
+        codec.ok_or(ErrorKind::UnsupportedCodec(self.container))
+    }
+}
+
+/// Output recorder: writes event frames to the video output (one file for the whole session or
+/// one per motion event) and keeps track of motion events for the event log.
+///
+/// # Fields
+/// * output: video output specification
+/// * writer: current video writer (`None` in clip mode if no event is in progress)
+/// * timestamps: frame timestamp sidecar writer of the current video writer
+/// * debug: debug video writer of the current video writer
+/// * event_log: event log writer
+/// * marker_log: fiducial marker log writer
+/// * event: motion event counter
+/// * skip_event: the event clip could not be opened, skip event frames
+/// * record: record of the event in progress
+/// * written: number of frames written to the video output
+/// * peak: snapshot of the event in progress (frame with the largest motion area)
+/// * contact_sheet: session contact sheet
+pub struct Recorder {
+    output: Output,
+    writer: Option<Writer>,
+    timestamps: Option<Timestamps>,
+    debug: Option<Writer>,
+    event_log: Option<EventLog>,
+    marker_log: Option<MarkerLog>,
+    event: u32,
+    skip_event: bool,
+    record: Option<EventRecord>,
+    written: u64,
+    peak: Option<Snapshot>,
+    contact_sheet: Option<ContactSheet>,
+}
+
+impl Recorder {
+    /// Create an instance of the Recorder: unless in clip mode, open the session output video
+    /// file, named after `start` date&time.
+    pub fn new(
+        output: Output,
+        start: &DateTime<Local>,
+        event_log: Option<EventLog>,
+    ) -> Result<Self, ErrorKind> {
+        let mut recorder = Self {
+            output,
+            writer: None,
+            timestamps: None,
+            debug: None,
+            event_log,
+            marker_log: None,
+            event: 0,
+            skip_event: false,
+            record: None,
+            written: 0,
+            peak: None,
+            contact_sheet: None,
+        };
+        if recorder.output.contact_sheet {
+            let path = recorder.output.path_with_extension(start, None, "contact");
+            recorder.contact_sheet = Some(ContactSheet::new(path));
+        }
+        if !recorder.output.clips {
+            recorder.open(start, None)?;
+        }
+
+        Ok(recorder)
+    }
+
+    /// Open the video writer (and its timestamp sidecar & debug video, if any) named after
+    /// `datetime` and `event` counter.
+    fn open(&mut self, datetime: &DateTime<Local>, event: Option<u32>) -> Result<(), ErrorKind> {
+        let writer = self.output.open(&self.output.path(datetime, event))?;
+        self.timestamps = match self.output.timestamps {
+            Some(format) => {
+                let path = self
+                    .output
+                    .path_with_extension(datetime, event, format.extension());
+                Some(Timestamps::create(
+                    Path::new(&path),
+                    format,
+                    self.output.fps,
+                )?)
+            }
+            None => None,
+        };
+        self.debug = match self.output.debug_video {
+            true => Some(self.output.open_debug(datetime, event)?),
+            false => None,
+        };
+        self.writer = Some(writer);
+
+        Ok(())
+    }
+
+    /// Log the fiducial markers decoded in every event frame to `marker_log`.
+    pub fn set_marker_log(&mut self, marker_log: MarkerLog) {
+        self.marker_log = Some(marker_log);
+    }
+
+    /// Return the number of frames written to the video output (across all event clips).
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Start a new motion event.
+    pub fn start(&mut self) {
+        self.event += 1;
+        self.skip_event = false;
+    }
+
+    /// Write event frame to the video output.
+    pub fn write(&mut self, mut detection: Detection) -> Result<(), ErrorKind> {
+        if self.skip_event {
+            return Ok(());
+        }
+
+        // Clip mode: open the event clip on its first frame, named after its date&time.
+        if self.writer.is_none() && self.output.clips {
+            if let Err(e) = self.open(&detection.frame.datetime, Some(self.event)) {
+                self.skip_event = true;
+                return Err(e);
+            }
+        }
+
+        // Copy the event frame with the largest motion area, before the overlay is printed on
+        // it: the snapshot is kept (or its error reported) once the frame is written.
+        let mut peak = None;
+        if self.output.snapshots || self.output.contact_sheet {
+            let area = detection.motion_area();
+            if self.peak.as_ref().map_or(true, |peak| area > peak.area()) {
+                peak = Some(Snapshot::new(&detection));
+            }
+        }
+
+        // Compose the frame & motion mask side by side for the debug video, written (or its error
+        // reported) once the frame is written.
+        let debug_frame = match self.debug {
+            Some(_) => detection.debug_frame(),
+            None => None,
+        };
+
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+
+        let datetime = detection.frame.datetime;
+        let motion_area = detection.is_motion().then(|| detection.motion_area());
+        let crossings = mem::take(&mut detection.crossings);
+        let tags = mem::take(&mut detection.tags);
+        let markers = mem::take(&mut detection.markers);
+
+        // Write processed frames (motion detected) to the video output.
+        writer.write_detection(detection)?;
+        self.written += 1;
+
+        // Map the written frame to its capture date&time.
+        let index = writer.frames() - 1;
+        if let Some(timestamps) = self.timestamps.as_mut() {
+            timestamps.write(index, &datetime)?;
+        }
+        if let Some(marker_log) = self.marker_log.as_mut() {
+            marker_log.write(self.event, index, &datetime, &markers)?;
+        }
+
+        // Update the event record with the written frame.
+        let record = self
+            .record
+            .get_or_insert_with(|| EventRecord::new(self.event, writer.path(), datetime, index));
+        record.push(datetime, motion_area, index);
+        record.count(&crossings);
+        record.tag(&tags);
+        record.mark(&markers);
+
+        // Debug video failure: stop writing the debug video (until the next clip, in clip mode).
+        let debug = match (self.debug.as_mut(), debug_frame) {
+            (Some(debug), Some(frame)) => frame.and_then(|frame| debug.write(frame)),
+            _ => Ok(()),
+        };
+        if debug.is_err() {
+            self.debug = None;
+        }
+
+        if let Some(peak) = peak {
+            self.peak = Some(peak?);
+        }
+
+        debug
+    }
+
+    /// End the motion event in progress (if any): write its record to the event log, save its
+    /// snapshot and, in clip mode, close the event clip.
+    pub fn end(&mut self) -> Result<(), ErrorKind> {
+        // Clip mode: close the event clip (dropping the writer releases it).
+        if self.output.clips {
+            self.writer = None;
+            self.timestamps = None;
+            self.debug = None;
+        }
+
+        let record = match self.record.take() {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        let snapshot = self.snapshot(&record);
+
+        if let Some(event_log) = self.event_log.as_mut() {
+            event_log.write(&record)?;
+        }
+
+        snapshot
+    }
+
+    /// Save the snapshot of the event `record` next to the event output, named as the event clip
+    /// (`<datetime formatted>_<event>.jpg`), and add it to the contact sheet.
+    fn snapshot(&mut self, record: &EventRecord) -> Result<(), ErrorKind> {
+        let peak = match self.peak.take() {
+            Some(peak) => peak,
+            None => return Ok(()),
+        };
+
+        if let Some(contact_sheet) = self.contact_sheet.as_mut() {
+            contact_sheet.push(&peak, record.event, self.output.snapshot_boxes)?;
+        }
+
+        if self.output.snapshots {
+            let path =
+                self.output
+                    .path_with_extension(&record.clip_start, Some(record.event), "jpg");
+            peak.save(&path, self.output.snapshot_boxes)?;
+        }
+
+        Ok(())
+    }
+
+    /// End the session: end the motion event in progress (if any) and save the last contact
+    /// sheet page (`<session datetime formatted>.contact.jpg`, then `.contact_<page>.jpg`).
+    pub fn finish(&mut self) -> Result<(), ErrorKind> {
+        let end = self.end();
+
+        if let Some(mut contact_sheet) = self.contact_sheet.take() {
+            contact_sheet.flush()?;
+        }
+
+        end
+    }
+}
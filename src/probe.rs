@@ -0,0 +1,178 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, eventlog::json_string, Grabber};
+use std::{cmp::Ordering, fmt::Write as _, fs};
+
+/// Candidate frame sizes (width, height) requested to the capture devices.
+const CANDIDATE_SIZES: [(i32, i32); 10] = [
+    (320, 240),
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 960),
+    (1920, 1080),
+    (2028, 1520),
+    (2592, 1944),
+    (3840, 2160),
+];
+
+/// Candidate framerates requested to the capture devices.
+const CANDIDATE_FPS: [i32; 5] = [15, 30, 60, 90, 120];
+
+/// Capture mode delivered by a capture device.
+///
+/// # Fields
+/// * width: frame width
+/// * height: frame height
+/// * fps: framerate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mode {
+    pub width: i32,
+    pub height: i32,
+    pub fps: f64,
+}
+
+/// Capture device and the capture modes it delivers.
+///
+/// # Fields
+/// * index: _/dev/video<index>_ capture camera index
+/// * modes: delivered capture modes, sorted by frame size & framerate
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub index: i32,
+    pub modes: Vec<Mode>,
+}
+
+impl Device {
+    /// Return the capture device path.
+    pub fn path(&self) -> String {
+        format!("/dev/video{}", self.index)
+    }
+}
+
+/// Return the sorted _/dev/video<index>_ capture camera indexes.
+pub fn devices() -> Vec<i32> {
+    let mut indexes: Vec<i32> = match fs::read_dir("/dev") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("video")?
+                    .parse()
+                    .ok()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    indexes.sort_unstable();
+
+    indexes
+}
+
+/// Probe the _/dev/video<index>_ capture device: request each candidate mode and read back the
+/// one OpenCV actually selected, collecting the distinct delivered modes.
+pub fn probe(index: i32) -> Result<Device, ErrorKind> {
+    let mut modes: Vec<Mode> = Vec::new();
+
+    for (width, height) in CANDIDATE_SIZES {
+        for fps in CANDIDATE_FPS {
+            let grabber = Grabber::new(index, height, width, fps)?;
+            let size = grabber.get_size();
+            // Device can't be opened (i.e. metadata node of a camera).
+            if size.width <= 0 || size.height <= 0 {
+                return Err(ErrorKind::InvalidCameraIndex);
+            }
+
+            let mode = Mode {
+                width: size.width,
+                height: size.height,
+                fps: grabber.get_fps(),
+            };
+            // Framerate reported by the driver may be garbage.
+            if mode.fps.is_finite() && !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+    }
+
+    // Framerates are finite, so they're always comparable.
+    modes.sort_by(|a, b| {
+        (a.width * a.height, a.width)
+            .cmp(&(b.width * b.height, b.width))
+            .then_with(|| a.fps.partial_cmp(&b.fps).unwrap_or(Ordering::Equal))
+    });
+
+    Ok(Device { index, modes })
+}
+
+/// Return the probed `devices` as table.
+pub fn table(devices: &[Device]) -> String {
+    let mut table = format!(
+        "{:<14} {:>6} {:>6} {:>7}\n",
+        "DEVICE", "WIDTH", "HEIGHT", "FPS"
+    );
+    for device in devices {
+        for mode in &device.modes {
+            let _ = writeln!(
+                table,
+                "{:<14} {:>6} {:>6} {:>7.2}",
+                device.path(),
+                mode.width,
+                mode.height,
+                mode.fps
+            );
+        }
+    }
+
+    table
+}
+
+/// Return the probed `devices` as JSON array.
+pub fn json(devices: &[Device]) -> String {
+    let devices: Vec<String> = devices
+        .iter()
+        .map(|device| {
+            let modes: Vec<String> = device
+                .modes
+                .iter()
+                .map(|mode| {
+                    // JSON has no NaN nor infinity.
+                    let fps = match mode.fps.is_finite() {
+                        true => mode.fps.to_string(),
+                        false => String::from("null"),
+                    };
+                    format!(
+                        "{{\"width\":{},\"height\":{},\"fps\":{fps}}}",
+                        mode.width, mode.height
+                    )
+                })
+                .collect();
+
+            format!(
+                "{{\"device\":{},\"index\":{},\"modes\":[{}]}}",
+                json_string(&device.path()),
+                device.index,
+                modes.join(",")
+            )
+        })
+        .collect();
+
+    format!("[{}]", devices.join(","))
+}
@@ -0,0 +1,226 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, Detection};
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Point, Rect, Scalar, Size, Vector, CV_8UC3},
+    imgcodecs::imwrite,
+    imgproc::{put_text, rectangle, resize, LineTypes, FONT_HERSHEY_DUPLEX, INTER_AREA},
+    prelude::{Mat, MatTraitConst},
+};
+
+/// Contact sheet thumbnail width.
+const THUMBNAIL_WIDTH: i32 = 320;
+
+/// Contact sheet columns.
+const CONTACT_SHEET_COLUMNS: usize = 4;
+
+/// Contact sheet rows per page: full pages are saved right away, bounding memory usage.
+const CONTACT_SHEET_ROWS: usize = 10;
+
+/// Event snapshot: the event frame with the largest motion area.
+///
+/// # Fields
+/// * frame: copy of the frame, as captured (no overlay)
+/// * datetime: frame date&time
+/// * area: frame motion area, as fraction of the frame area
+/// * rects: bounding rectangles of the frame motion contours
+#[derive(Debug)]
+pub struct Snapshot {
+    frame: Mat,
+    datetime: DateTime<Local>,
+    area: f64,
+    rects: Vec<Rect>,
+}
+
+impl Snapshot {
+    /// Create a snapshot of the `detection` frame.
+    pub fn new(detection: &Detection) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            frame: detection
+                .frame
+                .frame
+                .try_clone()
+                .map_err(|_| ErrorKind::SnapshotErr)?,
+            datetime: detection.frame.datetime,
+            area: detection.motion_area(),
+            rects: detection.blobs.iter().map(|blob| blob.rect).collect(),
+        })
+    }
+
+    /// Return the snapshot frame motion area, as fraction of the frame area.
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Return the snapshot image, with the motion contours bounding rectangles if `boxes`.
+    fn image(&self, boxes: bool) -> Result<Mat, ErrorKind> {
+        let mut image = self.frame.try_clone().map_err(|_| ErrorKind::SnapshotErr)?;
+        if boxes {
+            for rect in &self.rects {
+                rectangle(
+                    &mut image,
+                    *rect,
+                    Scalar::new(0., 255., 0., 0.), // Box color.
+                    2,                             // Thickness.
+                    LineTypes::LINE_8 as i32,      // Linetype.
+                    0,                             // Shift.
+                )
+                .map_err(|_| ErrorKind::SnapshotErr)?;
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Save the snapshot to `path` (image format given by the extension).
+    pub fn save(&self, path: &str, boxes: bool) -> Result<(), ErrorKind> {
+        match imwrite(path, &self.image(boxes)?, &Vector::new()) {
+            Ok(true) => Ok(()),
+            _ => Err(ErrorKind::ImageWriteErr(path.to_string())),
+        }
+    }
+}
+
+/// Session contact sheet: event snapshot thumbnails, labelled with event counter and date&time,
+/// tiled in rows, saved a page at a time.
+///
+/// # Fields
+/// * path: contact sheet path, without extension
+/// * thumbnails: thumbnails of the current page
+/// * pages: number of saved pages
+#[derive(Debug)]
+pub struct ContactSheet {
+    path: String,
+    thumbnails: Vec<Mat>,
+    pages: u32,
+}
+
+impl ContactSheet {
+    /// Create an empty contact sheet saved to `path` (without extension): the first page to
+    /// `<path>.jpg`, the following ones to `<path>_<page>.jpg`.
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            thumbnails: Vec::new(),
+            pages: 0,
+        }
+    }
+
+    /// Add the thumbnail of the `event` snapshot, saving the current page once full.
+    pub fn push(&mut self, snapshot: &Snapshot, event: u32, boxes: bool) -> Result<(), ErrorKind> {
+        let image = snapshot.image(boxes)?;
+        let size = image.size().map_err(|_| ErrorKind::SnapshotErr)?;
+        let height = (THUMBNAIL_WIDTH as f64 * size.height as f64 / size.width.max(1) as f64)
+            .round()
+            .max(1.) as i32;
+
+        let mut thumbnail = Mat::default();
+        resize(
+            &image,
+            &mut thumbnail,
+            Size::new(THUMBNAIL_WIDTH, height),
+            0.,
+            0.,
+            INTER_AREA,
+        )
+        .map_err(|_| ErrorKind::SnapshotErr)?;
+
+        // Label: text border, then text body.
+        let label = format!("#{event} {}", snapshot.datetime.format("%Y-%m-%d %H:%M:%S"));
+        for (color, thickness) in [(0., 3), (255., 1)] {
+            put_text(
+                &mut thumbnail,
+                &label,
+                Point::new(6, height - 8), // Bottom-left corner of the text string in the image.
+                FONT_HERSHEY_DUPLEX,       // Font type, see #hersheyfonts.
+                0.5, // Font scale factor that is multiplied by the font-specific base size.
+                Scalar::new(color, color, color, 1.), // Text color.
+                thickness, // Thickness.
+                LineTypes::LINE_8 as i32, // Linetype.
+                false,
+            )
+            .map_err(|_| ErrorKind::SnapshotErr)?;
+        }
+
+        self.thumbnails.push(thumbnail);
+        if self.thumbnails.len() == CONTACT_SHEET_COLUMNS * CONTACT_SHEET_ROWS {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Return true if the current page holds no thumbnail.
+    pub fn is_empty(&self) -> bool {
+        self.thumbnails.is_empty()
+    }
+
+    /// Save the current page (if not empty) and start a new one.
+    pub fn flush(&mut self) -> Result<(), ErrorKind> {
+        if self.thumbnails.is_empty() {
+            return Ok(());
+        }
+
+        self.pages += 1;
+        let path = match self.pages {
+            1 => format!("{}.jpg", self.path),
+            page => format!("{}_{page:04}.jpg", self.path),
+        };
+        let saved = self.save(&path);
+        self.thumbnails.clear();
+
+        saved
+    }
+
+    /// Tile the thumbnails of the current page and save them to `path`.
+    pub fn save(&self, path: &str) -> Result<(), ErrorKind> {
+        let tile = match self.thumbnails.first() {
+            Some(first) => first.size().map_err(|_| ErrorKind::SnapshotErr)?,
+            None => return Ok(()),
+        };
+        let columns = self.thumbnails.len().min(CONTACT_SHEET_COLUMNS);
+        let rows = (self.thumbnails.len() + columns - 1) / columns;
+
+        let sheet = Mat::new_rows_cols_with_default(
+            rows as i32 * tile.height,
+            columns as i32 * tile.width,
+            CV_8UC3,
+            Scalar::all(0.),
+        )
+        .map_err(|_| ErrorKind::SnapshotErr)?;
+
+        for (i, thumbnail) in self.thumbnails.iter().enumerate() {
+            let rect = Rect::new(
+                (i % columns) as i32 * tile.width,
+                (i / columns) as i32 * tile.height,
+                tile.width,
+                tile.height,
+            );
+            // The region of interest shares the contact sheet data.
+            let mut roi = Mat::roi(&sheet, rect).map_err(|_| ErrorKind::SnapshotErr)?;
+            thumbnail
+                .copy_to(&mut roi)
+                .map_err(|_| ErrorKind::SnapshotErr)?;
+        }
+
+        match imwrite(path, &sheet, &Vector::new()) {
+            Ok(true) => Ok(()),
+            _ => Err(ErrorKind::ImageWriteErr(path.to_string())),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+//! SORT (Simple Online and Realtime Tracking) building blocks: constant velocity Kalman filter
+//! on bounding boxes and optimal (Hungarian) assignment.
+
+use opencv::core::Rect;
+
+/// Initial position & velocity variances: velocities are unknown on the first detection.
+const INITIAL_VARIANCE: [f64; 2] = [10., 1e4];
+
+/// Process noise position & velocity variances.
+const PROCESS_NOISE: [f64; 2] = [1., 1e-2];
+
+/// Measurement noise variance of the box center (the box size is noisier).
+const CENTER_NOISE: f64 = 1.;
+
+/// Measurement noise variance of the box size.
+const SIZE_NOISE: f64 = 10.;
+
+/// Constant velocity Kalman filter of a single coordinate.
+///
+/// # Fields
+/// * x: state (position, velocity)
+/// * p: state covariance
+/// * r: measurement noise variance
+#[derive(Debug, Clone)]
+struct Kalman {
+    x: [f64; 2],
+    p: [[f64; 2]; 2],
+    r: f64,
+}
+
+impl Kalman {
+    /// Create a filter from the first measured position `z`.
+    fn new(z: f64, r: f64) -> Self {
+        Self {
+            x: [z, 0.],
+            p: [[INITIAL_VARIANCE[0], 0.], [0., INITIAL_VARIANCE[1]]],
+            r,
+        }
+    }
+
+    /// Advance the state by one frame, returning the predicted position.
+    fn predict(&mut self) -> f64 {
+        let [[p00, p01], [p10, p11]] = self.p;
+        self.x = [self.x[0] + self.x[1], self.x[1]];
+        // P = F P F' + Q, with F = [[1, 1], [0, 1]].
+        self.p = [
+            [p00 + p01 + p10 + p11 + PROCESS_NOISE[0], p01 + p11],
+            [p10 + p11, p11 + PROCESS_NOISE[1]],
+        ];
+
+        self.x[0]
+    }
+
+    /// Correct the state with the measured position `z`, returning the estimated position.
+    fn update(&mut self, z: f64) -> f64 {
+        let [[p00, p01], [p10, p11]] = self.p;
+        // Only the position is measured: H = [1, 0].
+        let s = p00 + self.r;
+        let k = [p00 / s, p10 / s];
+        let y = z - self.x[0];
+
+        self.x = [self.x[0] + k[0] * y, self.x[1] + k[1] * y];
+        self.p = [
+            [p00 - k[0] * p00, p01 - k[0] * p01],
+            [p10 - k[1] * p00, p11 - k[1] * p01],
+        ];
+
+        self.x[0]
+    }
+}
+
+/// Constant velocity Kalman filter of a bounding box: box center & size are filtered
+/// independently.
+#[derive(Debug, Clone)]
+pub struct KalmanBox {
+    filters: [Kalman; 4],
+}
+
+impl KalmanBox {
+    /// Create a filter from the first detected box.
+    pub fn new(rect: Rect) -> Self {
+        let [cx, cy, w, h] = measurement(rect);
+        Self {
+            filters: [
+                Kalman::new(cx, CENTER_NOISE),
+                Kalman::new(cy, CENTER_NOISE),
+                Kalman::new(w, SIZE_NOISE),
+                Kalman::new(h, SIZE_NOISE),
+            ],
+        }
+    }
+
+    /// Advance the filter by one frame, returning the predicted box.
+    pub fn predict(&mut self) -> Rect {
+        let state = [
+            self.filters[0].predict(),
+            self.filters[1].predict(),
+            self.filters[2].predict(),
+            self.filters[3].predict(),
+        ];
+
+        rect(state)
+    }
+
+    /// Correct the filter with the detected box, returning the estimated box.
+    pub fn update(&mut self, detected: Rect) -> Rect {
+        let z = measurement(detected);
+        let state = [
+            self.filters[0].update(z[0]),
+            self.filters[1].update(z[1]),
+            self.filters[2].update(z[2]),
+            self.filters[3].update(z[3]),
+        ];
+
+        rect(state)
+    }
+}
+
+/// Return the `rect` measurement: center & size.
+fn measurement(rect: Rect) -> [f64; 4] {
+    let (width, height) = (f64::from(rect.width), f64::from(rect.height));
+    [
+        f64::from(rect.x) + width / 2.,
+        f64::from(rect.y) + height / 2.,
+        width,
+        height,
+    ]
+}
+
+/// Return the box given its center & size.
+fn rect([cx, cy, width, height]: [f64; 4]) -> Rect {
+    let (width, height) = (width.max(1.), height.max(1.));
+    Rect::new(
+        (cx - width / 2.).round() as i32,
+        (cy - height / 2.).round() as i32,
+        width.round() as i32,
+        height.round() as i32,
+    )
+}
+
+/// Solve the assignment problem on the `cost` matrix (rows × columns), minimizing the total cost
+/// (Hungarian algorithm, O(n³)). Return the column assigned to each row: with more rows than
+/// columns, some rows are left unassigned.
+pub fn hungarian(cost: &[Vec<f64>]) -> Vec<Option<usize>> {
+    let rows = cost.len();
+    let columns = cost.first().map_or(0, Vec::len);
+    if rows == 0 || columns == 0 {
+        return vec![None; rows];
+    }
+
+    // The algorithm assigns every row: transpose if there are more rows than columns.
+    if rows > columns {
+        let transposed: Vec<Vec<f64>> = (0..columns)
+            .map(|j| (0..rows).map(|i| cost[i][j]).collect())
+            .collect();
+        let mut assignment = vec![None; rows];
+        for (j, i) in hungarian(&transposed).into_iter().enumerate() {
+            if let Some(i) = i {
+                assignment[i] = Some(j);
+            }
+        }
+        return assignment;
+    }
+
+    // Row & column potentials, column matching (1-based, 0 meaning unmatched).
+    let (n, m) = (rows, columns);
+    let mut u = vec![0.; n + 1];
+    let mut v = vec![0.; m + 1];
+    let mut matching = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+
+    for i in 1..=n {
+        matching[0] = i;
+        let mut j0 = 0;
+        let mut min = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+
+        // Grow the alternating tree until reaching a free column.
+        loop {
+            used[j0] = true;
+            let i0 = matching[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if reduced < min[j] {
+                    min[j] = reduced;
+                    way[j] = j0;
+                }
+                if min[j] < delta {
+                    delta = min[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[matching[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if matching[j0] == 0 {
+                break;
+            }
+        }
+
+        // Augment the matching along the path.
+        loop {
+            let j1 = way[j0];
+            matching[j0] = matching[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![None; rows];
+    for (j, &i) in matching.iter().enumerate().skip(1) {
+        if i != 0 {
+            assignment[i - 1] = Some(j - 1);
+        }
+    }
+
+    assignment
+}
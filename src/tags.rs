@@ -0,0 +1,166 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{error::ErrorKind, Blob};
+use opencv::{
+    core::{bitwise_or, count_non_zero, in_range, Rect, Scalar},
+    imgproc::{cvt_color, COLOR_BGR2HSV},
+    prelude::{Mat, MatTraitConst},
+};
+use serde::Deserialize;
+
+/// Default value for the minimum number of tag colour pixels inside a contour.
+fn default_min_pixels() -> u32 {
+    20
+}
+
+/// Maximum OpenCV 8-bit hue value (hue is halved to fit in 8 bits).
+const MAX_HUE: u8 = 179;
+
+/// Colour tag (paint dot) marking bumblebee individuals, as HSV colour range.
+///
+/// Hue ranges wrapping around 0 (i.e. red) are given with `lower` hue greater than `upper` hue.
+///
+/// # Fields
+/// * name: tag label, written to the event log
+/// * lower: lower HSV bound (OpenCV 8-bit ranges: hue 0-179, saturation & value 0-255)
+/// * upper: upper HSV bound
+/// * min_pixels: minimum number of pixels in range inside a contour to match the tag
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ColorTag {
+    pub name: String,
+    pub lower: [u8; 3],
+    pub upper: [u8; 3],
+    #[serde(default = "default_min_pixels")]
+    pub min_pixels: u32,
+}
+
+impl ColorTag {
+    /// Check colour tag consistency.
+    fn validate(&self) -> Result<(), ErrorKind> {
+        let invalid =
+            |msg: String| Err(ErrorKind::InvalidColorTag(format!("{}: {msg}", self.name)));
+
+        if self.name.is_empty() {
+            return Err(ErrorKind::InvalidColorTag(String::from(
+                "tag name must not be empty",
+            )));
+        }
+        if self.lower[0] > MAX_HUE || self.upper[0] > MAX_HUE {
+            return invalid(format!("hue must be in [0, {MAX_HUE}]"));
+        }
+        if self.lower[1] > self.upper[1] || self.lower[2] > self.upper[2] {
+            return invalid(String::from(
+                "lower saturation & value must not exceed the upper ones",
+            ));
+        }
+        if self.min_pixels == 0 {
+            return invalid(String::from("min_pixels must be at least 1"));
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of `hsv` image pixels within the tag colour range.
+    fn pixels(&self, hsv: &Mat) -> opencv::Result<u32> {
+        let bound = |hue: u8, [_, s, v]: [u8; 3]| {
+            Scalar::new(f64::from(hue), f64::from(s), f64::from(v), 0.)
+        };
+        let mut in_tag = Mat::default();
+
+        if self.lower[0] <= self.upper[0] {
+            in_range(
+                hsv,
+                &bound(self.lower[0], self.lower),
+                &bound(self.upper[0], self.upper),
+                &mut in_tag,
+            )?;
+        } else {
+            // Hue range wrapping around 0: [lower, MAX_HUE] or [0, upper].
+            let mut high = Mat::default();
+            let mut low = Mat::default();
+            in_range(
+                hsv,
+                &bound(self.lower[0], self.lower),
+                &bound(MAX_HUE, self.upper),
+                &mut high,
+            )?;
+            in_range(
+                hsv,
+                &bound(0, self.lower),
+                &bound(self.upper[0], self.upper),
+                &mut low,
+            )?;
+            bitwise_or(&high, &low, &mut in_tag, &Mat::default())?;
+        }
+
+        Ok(count_non_zero(&in_tag)? as u32)
+    }
+}
+
+/// Colour tag classifier: looks for the configured colour tags inside the motion contours
+/// bounding rectangles.
+///
+/// # Fields
+/// * tags: colour tags to look for
+#[derive(Debug, Clone)]
+pub struct TagClassifier {
+    tags: Vec<ColorTag>,
+}
+
+impl TagClassifier {
+    /// Create an instance of the TagClassifier, return Err if `tags` are not valid.
+    pub fn new(tags: Vec<ColorTag>) -> Result<Self, ErrorKind> {
+        for tag in &tags {
+            tag.validate()?;
+        }
+
+        Ok(Self { tags })
+    }
+
+    /// Return the names of the tags found inside any of the `blobs` in `frame` (BGR), in
+    /// configuration order.
+    pub fn classify(&self, frame: &Mat, blobs: &[Blob]) -> Result<Vec<String>, ErrorKind> {
+        let mut found = vec![false; self.tags.len()];
+        let bounds = Rect::new(0, 0, frame.cols(), frame.rows());
+
+        for blob in blobs {
+            let rect = blob.rect & bounds;
+            if rect.area() == 0 {
+                continue;
+            }
+
+            let mut hsv = Mat::default();
+            Mat::roi(frame, rect)
+                .and_then(|region| cvt_color(&region, &mut hsv, COLOR_BGR2HSV, 0))
+                .map_err(|_| ErrorKind::ColorTagErr)?;
+            for (tag, found) in self.tags.iter().zip(found.iter_mut()) {
+                if !*found {
+                    *found =
+                        tag.pixels(&hsv).map_err(|_| ErrorKind::ColorTagErr)? >= tag.min_pixels;
+                }
+            }
+        }
+
+        Ok(self
+            .tags
+            .iter()
+            .zip(found)
+            .filter(|(_, found)| *found)
+            .map(|(tag, _)| tag.name.clone())
+            .collect())
+    }
+}
@@ -1,7 +1,36 @@
-use crate::{Codec, Config, Grabber, Local, MotionDetector, Path, Writer};
-use bombuscv_rs::Frame;
+use crate::{Args, Config, Grabber, Local, MotionDetector, Parser};
+use bombuscv_rs::{
+    buffer::PreRoll,
+    classifier::Classifier,
+    event::{EventStateMachine, State, Transition},
+    eventlog::{csv_field, json_string, EventRecord},
+    gstreamer_available,
+    images::{self, ImageFormat},
+    input,
+    markers::{Marker, MarkerDictionary, MarkerParams, MarkerReader},
+    parse_filename_datetime, probe,
+    snapshot::{ContactSheet, Snapshot},
+    sort::hungarian,
+    tags::{ColorTag, TagClassifier},
+    timestamps::{cue_time, TimestampFormat, Timestamps},
+    tracker::{Boundary, Direction, Tracker, TrackerMethod, TrackerParams},
+    AppliedControl, AreaBound, Blob, Codec, Container, Detection, DetectorParams, Frame, Span,
+    Writer,
+};
+use chrono::SecondsFormat;
 use directories::BaseDirs;
-use std::{fs, time::Instant};
+use opencv::{
+    core::{Rect, Size},
+    prelude::{Mat, MatTraitConst},
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpListener,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
 #[test]
 fn sync_frame_processing_avg_time() {
@@ -23,6 +52,7 @@ fn sync_frame_processing_avg_time() {
         overlay: false,
         no_color: true,
         quiet: false,
+        ..Config::default()
     };
 
     // Format video file path as <config.directory/date&time>.
@@ -32,7 +62,7 @@ fn sync_frame_processing_avg_time() {
                 // Output video file directory.
                 .directory
                 // Output video file name (derived by file format) + extension.
-                .join(Path::new(&config.format).with_extension("mkv"))
+                .join(Path::new(&config.format).with_extension(config.container.extension()))
                 // Convert Path object to string;
                 .to_str()
                 .unwrap(),
@@ -46,7 +76,7 @@ fn sync_frame_processing_avg_time() {
     // Instance of the frame grabber.
     let mut grabber = match &config.video {
         // VideoCapture is video file.
-        Some(video) => Grabber::from_file(video),
+        Some(video) => Grabber::from_file(video, config.video_start, &config.format),
         // VideoCapture is live camera.
         None => Grabber::new(
             config.index.into(),
@@ -58,15 +88,16 @@ fn sync_frame_processing_avg_time() {
     .unwrap();
 
     // Instance of the motion detector.
-    let mut detector = MotionDetector::new();
+    let mut detector = MotionDetector::new(config.detector.clone()).unwrap();
 
     // Instance of the frame writer.
     let mut writer = Writer::new(
         &filename,
-        Codec::XVID,
+        config.codec,
         grabber.get_fps(),
         grabber.get_size(),
         config.overlay,
+        config.overlay_border,
     )
     .unwrap();
 
@@ -84,10 +115,10 @@ fn sync_frame_processing_avg_time() {
     let start = Instant::now();
     for frame in frames {
         match detector.detect_motion(frame) {
-            Ok(frame) => {
-                if let Some(frame) = frame {
+            Ok(detection) => {
+                if detection.is_motion() {
                     // If frame is detected, write it to the file.
-                    writer.write(frame).unwrap();
+                    writer.write(detection.frame).unwrap();
                     // Count the detected frames.
                     detected_frames += 1;
                 }
@@ -113,3 +144,961 @@ fn sync_frame_processing_avg_time() {
 
     assert!(dur_ns.subsec_micros() <= (max * 1e3) as u32);
 }
+
+#[test]
+fn detector_params_validation() {
+    assert!(DetectorParams::default().validate().is_ok());
+
+    // Even kernel sizes are rejected by OpenCV's gaussian_blur.
+    let params = DetectorParams {
+        kernel_size: 4,
+        ..DetectorParams::default()
+    };
+    assert!(params.validate().is_err());
+
+    let params = DetectorParams {
+        width: 0,
+        ..DetectorParams::default()
+    };
+    assert!(params.validate().is_err());
+
+    let params = DetectorParams {
+        sigma: -1.,
+        ..DetectorParams::default()
+    };
+    assert!(params.validate().is_err());
+
+    // Integers are pixels, floats are fractions of the working frame area.
+    assert_eq!("120".parse(), Ok(AreaBound::Pixels(120)));
+    assert_eq!("0.5".parse(), Ok(AreaBound::Fraction(0.5)));
+
+    let params = DetectorParams {
+        min_area: Some(AreaBound::Fraction(1.5)),
+        ..DetectorParams::default()
+    };
+    assert!(params.validate().is_err());
+
+    let params = DetectorParams {
+        min_area: Some(AreaBound::Pixels(500)),
+        max_area: Some(AreaBound::Fraction(0.001)),
+        ..DetectorParams::default()
+    };
+    assert!(params.validate().is_err());
+}
+
+#[test]
+fn input_params_validation() {
+    assert!(Config::default().validate().is_ok());
+    for config in [
+        "reconnect_backoff = nan",
+        "reconnect_backoff = -1.0",
+        "stream_timeout = inf",
+    ] {
+        assert!(toml::from_str::<Config>(config)
+            .unwrap()
+            .validate()
+            .is_err());
+    }
+}
+
+#[test]
+fn camera_controls_report() {
+    let report = |set, read_back| AppliedControl::new("gain", 50., set, read_back).to_string();
+
+    assert_eq!(report(true, Some(50.)), "gain = 50");
+    assert_eq!(
+        report(true, Some(48.)),
+        "gain = 48 (requested 50, adjusted by the driver)"
+    );
+    // Rejected value, or control not readable.
+    assert_eq!(report(false, Some(0.)), "gain unsupported (requested 50)");
+    assert_eq!(report(true, None), "gain unsupported (requested 50)");
+    // Rejected value, already in place.
+    assert_eq!(report(false, Some(50.)), "gain = 50");
+}
+
+#[test]
+fn cli_input_override() {
+    let config = || toml::from_str::<Config>("url = \"rtsp://192.168.1.10/stream\"").unwrap();
+    let args = |args: &[&str]| Args::parse_from([&["bombuscv"], args].concat());
+
+    // Configured network stream, unless another input is given on the command line.
+    assert!(config().override_with_args(args(&[])).url.is_some());
+    let overridden = config().override_with_args(args(&["--index", "2"]));
+    assert_eq!((overridden.url, overridden.index), (None, 2));
+    let overridden = config().override_with_args(args(&["--url", "http://cam/mjpg"]));
+    assert_eq!(overridden.url.as_deref(), Some("http://cam/mjpg"));
+}
+
+#[test]
+fn cli_input_pipeline_override() {
+    let config =
+        || toml::from_str::<Config>("input_pipeline = \"libcamerasrc ! appsink\"").unwrap();
+    let args = |args: &[&str]| Args::parse_from([&["bombuscv"], args].concat());
+
+    assert!(config()
+        .override_with_args(args(&[]))
+        .input_pipeline
+        .is_some());
+    let overridden = config().override_with_args(args(&["--index", "1"]));
+    assert_eq!((overridden.input_pipeline, overridden.index), (None, 1));
+    let video = std::env::temp_dir();
+    let overridden = config().override_with_args(args(&["--video", video.to_str().unwrap()]));
+    assert_eq!(overridden.input_pipeline, None);
+}
+
+#[test]
+fn output_pipeline_validation() {
+    let config = |pipeline: &str, clips| Config {
+        output_pipeline: Some(pipeline.to_string()),
+        clips,
+        ..Config::default()
+    };
+    let sink = "appsrc ! videoconvert ! x264enc ! matroskamux ! filesink location=";
+
+    assert!(config(&format!("{sink}out.mkv"), false).validate().is_ok());
+    assert!(config(&format!("{sink}{{path}}"), true).validate().is_ok());
+    // Every clip would be written to the same file.
+    assert!(config(&format!("{sink}out.mkv"), true).validate().is_err());
+    // Same with batch input (directory).
+    let batch = Config {
+        video: Some(std::env::temp_dir()),
+        ..config(&format!("{sink}out.mkv"), false)
+    };
+    assert!(batch.validate().is_err());
+}
+
+#[test]
+fn pre_roll_buffer() {
+    assert_eq!("30".parse(), Ok(Span::Frames(30)));
+    assert_eq!("1.5s".parse(), Ok(Span::Seconds(1.5)));
+    assert_eq!(Span::Seconds(1.5).frames(30.), 45);
+
+    // Oldest items are dropped once the buffer is full.
+    let mut pre_roll = PreRoll::new(3);
+    for i in 0..5 {
+        pre_roll.push(i);
+    }
+    assert_eq!(pre_roll.drain().collect::<Vec<_>>(), vec![2, 3, 4]);
+    assert!(pre_roll.is_empty());
+
+    // Zero capacity disables buffering.
+    let mut pre_roll = PreRoll::new(0);
+    pre_roll.push(0);
+    assert!(pre_roll.is_empty());
+}
+
+#[test]
+fn event_state_machine() {
+    // Events start after 2 consecutive motion frames and end after 2 frames without motion.
+    let mut events = EventStateMachine::new(2, 2);
+
+    // Single motion frame is not enough to start an event.
+    assert_eq!(events.update(true), Transition::None);
+    assert_eq!(events.state(), State::Triggered(1));
+    assert_eq!(events.update(false), Transition::None);
+    assert_eq!(events.state(), State::Idle);
+
+    assert_eq!(events.update(true), Transition::None);
+    assert_eq!(events.update(true), Transition::Start);
+    assert!(events.is_recording());
+
+    // Motion during cooldown resumes recording.
+    assert_eq!(events.update(false), Transition::None);
+    assert_eq!(events.state(), State::Cooldown(1));
+    assert_eq!(events.update(true), Transition::None);
+    assert_eq!(events.state(), State::Recording);
+
+    // Post-roll frames are recorded, then the event ends.
+    assert_eq!(events.update(false), Transition::None);
+    assert_eq!(events.update(false), Transition::None);
+    assert!(events.is_recording());
+    assert_eq!(events.update(false), Transition::End);
+    assert!(!events.is_recording());
+}
+
+#[test]
+fn event_record() {
+    let at = |second: u32| {
+        let stem = format!("2022-06-23T11:49:0{second}");
+        parse_filename_datetime(Path::new(&stem), "%Y-%m-%dT%H:%M:%S").unwrap()
+    };
+    let rfc3339 = |second| at(second).to_rfc3339_opts(SecondsFormat::Millis, false);
+
+    // Pre-roll frame, 2 motion frames & post-roll frame.
+    let mut record = EventRecord::new(3, "out/a,\"b\".mkv", at(0), 10);
+    record.push(at(0), None, 10);
+    record.push(at(1), Some(0.25), 11);
+    record.push(at(2), Some(0.5), 12);
+    record.push(at(3), None, 13);
+    record.count_in = 1;
+    record.tag(&[String::from("red"), String::from("blü")]);
+    record.tag(&[String::from("red")]);
+    let marker = |id| Marker { id, x: 0., y: 0. };
+    record.mark(&[marker(7), marker(3), marker(7)]);
+
+    // Motion span excludes the padding, clip span includes it.
+    assert_eq!((record.start, record.end), (at(1), at(2)));
+    assert_eq!((record.clip_start, record.clip_end), (at(0), at(3)));
+    assert_eq!(record.frames, 2);
+    assert_eq!(record.duration(), 1.);
+
+    assert_eq!(
+        record.to_json(),
+        format!(
+            "{{\"event\":3,\"start\":\"{}\",\"end\":\"{}\",\"duration\":1.000,\"frames\":2,\
+             \"peak_area\":0.500000,\"file\":\"out/a,\\\"b\\\".mkv\",\"clip_start\":\"{}\",\
+             \"clip_end\":\"{}\",\"first_frame\":10,\"last_frame\":13,\"in\":1,\"out\":0,\
+             \"tags\":[\"red\",\"blü\"],\"markers\":[7,3]}}",
+            rfc3339(1),
+            rfc3339(2),
+            rfc3339(0),
+            rfc3339(3)
+        )
+    );
+    assert_eq!(
+        record.to_csv(),
+        format!(
+            "3,{},{},1.000,2,0.500000,\"out/a,\"\"b\"\".mkv\",{},{},10,13,1,0,red;blü,7;3",
+            rfc3339(1),
+            rfc3339(2),
+            rfc3339(0),
+            rfc3339(3)
+        )
+    );
+}
+
+#[test]
+fn cue_times() {
+    assert_eq!(cue_time(0, ','), "00:00:00,000");
+    assert_eq!(cue_time(59_999, '.'), "00:00:59.999");
+    assert_eq!(cue_time(3_599_999, ','), "00:59:59,999");
+    assert_eq!(cue_time(3_600_000, ','), "01:00:00,000");
+    assert_eq!(cue_time(36_061_001, '.'), "10:01:01.001");
+}
+
+#[test]
+fn timestamp_sidecars() {
+    let at = |second: u32| {
+        let stem = format!("2022-06-23T11:49:0{second}");
+        parse_filename_datetime(Path::new(&stem), "%Y-%m-%dT%H:%M:%S").unwrap()
+    };
+    // First frame, then last frame of the first hour at 30 fps: its cue ends on the hour.
+    let sidecar = |format| {
+        let path = std::env::temp_dir().join("bombuscv-timestamps");
+        let mut timestamps = Timestamps::create(&path, format, 30.).unwrap();
+        timestamps.write(0, &at(0)).unwrap();
+        timestamps.write(107_999, &at(1)).unwrap();
+        drop(timestamps);
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        content
+    };
+
+    assert_eq!(
+        sidecar(TimestampFormat::Srt),
+        "1\n00:00:00,000 --> 00:00:00,033\n2022-06-23 11:49:00.000\n\n\
+         108000\n00:59:59,967 --> 01:00:00,000\n2022-06-23 11:49:01.000\n\n"
+    );
+    assert_eq!(
+        sidecar(TimestampFormat::Vtt),
+        "WEBVTT\n\n00:00:00.000 --> 00:00:00.033\n2022-06-23 11:49:00.000\n\
+         \n00:59:59.967 --> 01:00:00.000\n2022-06-23 11:49:01.000\n"
+    );
+    assert_eq!(
+        sidecar(TimestampFormat::Csv),
+        format!(
+            "frame,position,datetime\n0,0.000,{}\n107999,3599.967,{}\n",
+            at(0).to_rfc3339_opts(SecondsFormat::Millis, false),
+            at(1).to_rfc3339_opts(SecondsFormat::Millis, false)
+        )
+    );
+}
+
+#[test]
+fn event_log_fields() {
+    assert_eq!(json_string("bombus"), "\"bombus\"");
+    assert_eq!(
+        json_string("a \"b\" c\\d\ne\tf\u{1}"),
+        "\"a \\\"b\\\" c\\\\d\\ne\\tf\\u0001\""
+    );
+    assert_eq!(json_string("blü, 熊蜂"), "\"blü, 熊蜂\"");
+
+    assert_eq!(csv_field("bombus"), "bombus");
+    assert_eq!(csv_field("blü;熊蜂"), "blü;熊蜂");
+    assert_eq!(csv_field("a,b"), "\"a,b\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+}
+
+#[test]
+fn codec_container() {
+    assert_eq!("H264".parse::<Codec>(), Ok(Codec::H264));
+    assert_eq!("mp4v".parse::<Codec>(), Ok(Codec::MP4V));
+    assert!("divx".parse::<Codec>().is_err());
+    // Configuration file too is case-insensitive.
+    let config: Config = toml::from_str("codec = \"MJPG\"").unwrap();
+    assert_eq!(config.codec, Codec::MJPG);
+    assert!(toml::from_str::<Config>("codec = \"divx\"").is_err());
+    assert_eq!("mp4".parse::<Container>(), Ok(Container::Mp4));
+    assert_eq!("MKV".parse::<Container>(), Ok(Container::Mkv));
+    assert!("mov".parse::<Container>().is_err());
+    let config: Config = toml::from_str("container = \"Avi\"").unwrap();
+    assert_eq!(config.container, Container::Avi);
+    let args = Args::parse_from(["bombuscv", "--codec", "MP4V", "--container", "MP4"]);
+    assert_eq!(
+        (args.codec, args.container),
+        (Some(Codec::MP4V), Some(Container::Mp4))
+    );
+
+    // Default codec/container combination is valid.
+    assert!(Container::default().check(Codec::default()).is_ok());
+    assert!(Container::Mp4.check(Codec::H264).is_ok());
+    assert!(Container::Mp4.check(Codec::XVID).is_err());
+    assert!(Container::Avi.check(Codec::MJPG).is_ok());
+}
+
+#[test]
+fn filename_datetime() {
+    let datetime = parse_filename_datetime(
+        Path::new("/videos/2022-06-23T11:49:00.mkv"),
+        "%Y-%m-%dT%H:%M:%S",
+    )
+    .unwrap();
+    assert_eq!(
+        datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        "2022-06-23 11:49:00"
+    );
+
+    // Date-only format: midnight.
+    let datetime = parse_filename_datetime(Path::new("nest_2022-06-23.mp4"), "nest_%Y-%m-%d");
+    assert_eq!(datetime.unwrap().format("%H:%M").to_string(), "00:00");
+
+    assert!(parse_filename_datetime(Path::new("clip.mkv"), "%Y-%m-%dT%H:%M:%S").is_none());
+}
+
+#[test]
+fn exif_datetime() {
+    // Little-endian TIFF structure: IFD0 holding the DateTime tag only.
+    let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+    tiff.extend([1, 0, 0x32, 0x01, 2, 0, 20, 0, 0, 0, 26, 0, 0, 0, 0, 0, 0, 0]);
+    tiff.extend(b"2022:06:23 11:49:00\0");
+
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend((tiff.len() as u16 + 8).to_be_bytes());
+    jpeg.extend(b"Exif\0\0");
+    jpeg.extend(&tiff);
+    jpeg.extend([0xFF, 0xDA]);
+
+    assert_eq!(
+        images::exif_datetime(&jpeg).unwrap().to_string(),
+        "2022-06-23 11:49:00"
+    );
+    // No EXIF segment.
+    assert!(images::exif_datetime(&[0xFF, 0xD8, 0xFF, 0xDA]).is_none());
+    assert!(images::exif_datetime(b"not a jpeg").is_none());
+}
+
+#[test]
+fn image_sequence_files() {
+    let directory = std::env::temp_dir().join("bombuscv-images");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir(&directory).unwrap();
+    for name in [
+        "IMG_0007.JPG",
+        "IMG_0012.JPG",
+        "IMG_10000.JPG",
+        "IMG_0002.JPG",
+        "IMG_7.JPG",
+        "IMG_abcd.JPG",
+        "notes.txt",
+    ] {
+        fs::write(directory.join(name), "").unwrap();
+    }
+    let names = |files: Vec<std::path::PathBuf>| {
+        files
+            .iter()
+            .map(|file| file.file_name().unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Pattern: sorted by number, starting anywhere, gaps allowed.
+    assert_eq!(
+        names(images::image_files(&directory.join("IMG_%04d.JPG")).unwrap()),
+        [
+            "IMG_0002.JPG",
+            "IMG_0007.JPG",
+            "IMG_0012.JPG",
+            "IMG_10000.JPG"
+        ]
+    );
+    assert_eq!(
+        names(images::image_files(&directory.join("IMG_%d.JPG")).unwrap()),
+        [
+            "IMG_0002.JPG",
+            "IMG_0007.JPG",
+            "IMG_7.JPG",
+            "IMG_0012.JPG",
+            "IMG_10000.JPG"
+        ]
+    );
+    // Directory: natural order.
+    assert_eq!(
+        names(images::image_files(&directory).unwrap()),
+        [
+            "IMG_0002.JPG",
+            "IMG_7.JPG",
+            "IMG_0007.JPG",
+            "IMG_0012.JPG",
+            "IMG_10000.JPG",
+            "IMG_abcd.JPG"
+        ]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn contact_sheet_pages() {
+    use opencv::{
+        core::{Scalar, CV_8UC3},
+        imgcodecs::{imread, IMREAD_COLOR},
+    };
+
+    let stem = std::env::temp_dir().join("bombuscv-session.contact");
+    let stem = stem.to_str().unwrap();
+    let mut contact_sheet = ContactSheet::new(stem.to_string());
+
+    // 640x480 frames: 320x240 thumbnails, 4 columns & 10 rows per page.
+    let frame = Frame {
+        frame: Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(128.)).unwrap(),
+        datetime: Local::now(),
+    };
+    let blobs = vec![Blob {
+        area: 100.,
+        rect: Rect::new(10, 10, 10, 10),
+    }];
+    let snapshot = Snapshot::new(&Detection::from_blobs(frame, 0, blobs, 640. * 480.)).unwrap();
+    let size = |path: &str| {
+        let page = imread(path, IMREAD_COLOR).unwrap();
+        (page.cols(), page.rows())
+    };
+
+    // First page saved once full.
+    for event in 1..=40 {
+        contact_sheet.push(&snapshot, event, true).unwrap();
+    }
+    assert!(contact_sheet.is_empty());
+    assert_eq!(size(&format!("{stem}.jpg")), (4 * 320, 10 * 240));
+
+    // Partial last page: 2 rows, the second one with a single thumbnail.
+    for event in 41..=46 {
+        contact_sheet.push(&snapshot, event, true).unwrap();
+    }
+    contact_sheet.flush().unwrap();
+    assert_eq!(size(&format!("{stem}_0002.jpg")), (4 * 320, 2 * 240));
+    // Single thumbnail page: single column.
+    contact_sheet.push(&snapshot, 47, false).unwrap();
+    contact_sheet.flush().unwrap();
+    assert_eq!(size(&format!("{stem}_0003.jpg")), (320, 240));
+
+    for page in [".jpg", "_0002.jpg", "_0003.jpg"] {
+        fs::remove_file(format!("{stem}{page}")).unwrap();
+    }
+}
+
+#[test]
+fn debug_frame() {
+    use opencv::core::{Scalar, CV_8UC1, CV_8UC3};
+
+    let frame = Frame {
+        frame: Mat::new_rows_cols_with_default(240, 320, CV_8UC3, Scalar::all(0.)).unwrap(),
+        datetime: Local::now(),
+    };
+    let mut detection = Detection::from_blobs(frame, 0, Vec::new(), 320. * 240.);
+    // Mask not kept by the detector.
+    assert!(detection.debug_frame().is_none());
+
+    // Working size mask, upscaled to the frame size: debug frame twice as wide.
+    detection.mask =
+        Some(Mat::new_rows_cols_with_default(120, 160, CV_8UC1, Scalar::all(255.)).unwrap());
+    let debug = detection.debug_frame().unwrap().unwrap();
+    assert_eq!(debug.frame.size().unwrap(), Size::new(640, 240));
+    assert_eq!(debug.datetime, detection.frame.datetime);
+}
+
+#[test]
+fn write_detection() {
+    use opencv::{
+        core::{Scalar, Vec3b, CV_8UC3},
+        imgcodecs::{imread, IMREAD_COLOR},
+    };
+
+    let directory = std::env::temp_dir().join("bombuscv-write-detection");
+    let _ = fs::remove_dir_all(&directory);
+    let mut writer =
+        Writer::images(directory.to_str().unwrap(), ImageFormat::Png, false, 0).unwrap();
+    writer.set_debug_overlay(true);
+
+    let blob = |x, y| Blob {
+        area: 400.,
+        rect: Rect::new(x, y, 20, 20),
+    };
+    let frame = Frame {
+        frame: Mat::new_rows_cols_with_default(240, 320, CV_8UC3, Scalar::all(0.)).unwrap(),
+        datetime: Local::now(),
+    };
+    let mut detection = Detection::from_blobs(frame, 0, vec![blob(100, 120)], 320. * 240.);
+    detection.rejected = vec![blob(200, 120)];
+    writer.write_detection(detection).unwrap();
+    assert_eq!(writer.frames(), 1);
+
+    // Lossless image: accepted contour box in green, rejected one in red.
+    let file = fs::read_dir(&directory).unwrap().next().unwrap().unwrap();
+    let image = imread(file.path().to_str().unwrap(), IMREAD_COLOR).unwrap();
+    assert_eq!(image.size().unwrap(), Size::new(320, 240));
+    assert_eq!(image.at_2d::<Vec3b>(120, 100).unwrap().0, [0, 255, 0]);
+    assert_eq!(image.at_2d::<Vec3b>(120, 200).unwrap().0, [0, 0, 255]);
+    assert_eq!(image.at_2d::<Vec3b>(130, 150).unwrap().0, [0, 0, 0]);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn boundary_crossing() {
+    // Horizontal line, left to right: moving down crosses it from left to right (in).
+    let line = Boundary::Line([[0, 100], [200, 100]]);
+    assert_eq!(line.crossing((50., 90.), (50., 110.)), Some(Direction::In));
+    assert_eq!(line.crossing((50., 110.), (50., 90.)), Some(Direction::Out));
+    // Beyond the line segment end.
+    assert_eq!(line.crossing((250., 90.), (250., 110.)), None);
+
+    let polygon = Boundary::Polygon(vec![[0, 0], [100, 0], [100, 100], [0, 100]]);
+    assert_eq!(
+        polygon.crossing((150., 50.), (50., 50.)),
+        Some(Direction::In)
+    );
+    assert_eq!(polygon.crossing((50., 50.), (60., 50.)), None);
+}
+
+#[test]
+fn tracker_counts() {
+    let mut tracker = Tracker::new(TrackerParams {
+        boundary: Some(Boundary::Line([[0, 100], [200, 100]])),
+        ..TrackerParams::default()
+    })
+    .unwrap();
+
+    // A single blob moving down across the line, 20 pixels per frame.
+    let crossings: Vec<_> = (0..6)
+        .map(|i| {
+            let frame = Frame {
+                frame: Mat::default(),
+                datetime: Local::now(),
+            };
+            let blobs = vec![Blob {
+                area: 100.,
+                rect: Rect::new(40, 50 + 20 * i as i32, 20, 20),
+            }];
+            let mut detection = Detection::from_blobs(frame, i, blobs, 1e4);
+            tracker.update(&mut detection);
+            detection.crossings
+        })
+        .collect();
+
+    assert_eq!(crossings.iter().map(Vec::len).sum::<usize>(), 1);
+    assert!(crossings[2]
+        .iter()
+        .all(|c| c.track == 1 && c.direction == Direction::In));
+    assert_eq!(tracker.counts().total(), (1, 0));
+    for min_iou in [0., 2., f64::NAN] {
+        assert!(TrackerParams {
+            min_iou,
+            ..TrackerParams::default()
+        }
+        .validate()
+        .is_err());
+    }
+    for max_distance in [-1., f64::NAN] {
+        assert!(TrackerParams {
+            max_distance,
+            ..TrackerParams::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
+
+#[test]
+fn hungarian_assignment() {
+    // Greedy assignment (row 0 to column 0) would cost 1 + 7 = 8 rather than 2 + 2 = 4.
+    let cost = vec![vec![1., 2.], vec![2., 7.]];
+    assert_eq!(hungarian(&cost), vec![Some(1), Some(0)]);
+    // More rows than columns: the costliest row is left unassigned.
+    let cost = vec![vec![5.], vec![1.], vec![3.]];
+    assert_eq!(hungarian(&cost), vec![None, Some(0), None]);
+    assert!(hungarian(&[]).is_empty());
+}
+
+#[test]
+fn sort_tracks() {
+    let mut tracker = Tracker::new(TrackerParams {
+        method: TrackerMethod::Sort,
+        min_hits: 2,
+        ..TrackerParams::default()
+    })
+    .unwrap();
+
+    // Two blobs moving towards each other, 15 pixels per frame.
+    let tracks: Vec<_> = (0..8)
+        .map(|i| {
+            let step = 15 * i as i32;
+            let frame = Frame {
+                frame: Mat::default(),
+                datetime: Local::now(),
+            };
+            let blobs = vec![
+                Blob {
+                    area: 100.,
+                    rect: Rect::new(step, 100, 20, 20),
+                },
+                Blob {
+                    area: 100.,
+                    rect: Rect::new(300 - step, 100, 20, 20),
+                },
+            ];
+            let mut detection = Detection::from_blobs(frame, i, blobs, 1e5);
+            tracker.update(&mut detection);
+            detection.tracks
+        })
+        .collect();
+
+    // Tracks are confirmed on the second frame and keep their IDs.
+    assert!(tracks[0].is_empty());
+    for points in &tracks[1..] {
+        assert_eq!(points.len(), 2);
+        assert!(points.iter().all(|p| p.area == 1e-3));
+        let left = points.iter().find(|p| p.track == 1).unwrap();
+        let right = points.iter().find(|p| p.track == 2).unwrap();
+        assert!(left.rect.x < right.rect.x);
+    }
+}
+
+#[test]
+fn color_tags_validation() {
+    let tag = |lower: [u8; 3], upper: [u8; 3]| ColorTag {
+        name: String::from("red"),
+        lower,
+        upper,
+        min_pixels: 20,
+    };
+
+    // Hue range wrapping around 0.
+    assert!(TagClassifier::new(vec![tag([170, 100, 100], [10, 255, 255])]).is_ok());
+    // Hue out of range.
+    assert!(TagClassifier::new(vec![tag([170, 100, 100], [190, 255, 255])]).is_err());
+    // Saturation range reversed.
+    assert!(TagClassifier::new(vec![tag([0, 200, 100], [10, 100, 255])]).is_err());
+    assert!(TagClassifier::new(vec![ColorTag {
+        min_pixels: 0,
+        ..tag([0, 100, 100], [10, 255, 255])
+    }])
+    .is_err());
+}
+
+#[test]
+fn color_tags_matching() {
+    use opencv::{
+        core::{Scalar, CV_8UC3},
+        imgproc::{rectangle, FILLED, LINE_8},
+    };
+
+    let tag = |name: &str, lower: [u8; 3], upper: [u8; 3]| ColorTag {
+        name: String::from(name),
+        lower,
+        upper,
+        min_pixels: 20,
+    };
+    let classifier = TagClassifier::new(vec![
+        tag("yellow", [20, 100, 100], [40, 255, 255]),
+        tag("red", [170, 100, 100], [10, 255, 255]),
+    ])
+    .unwrap();
+    let blob = |x: i32| Blob {
+        area: 400.,
+        rect: Rect::new(x, 40, 20, 20),
+    };
+
+    // Yellow patch (hue 30) and crimson patch (hue ~175, above the wrapped red range start),
+    // both BGR, on a black frame.
+    let mut frame = Mat::new_rows_cols_with_default(100, 200, CV_8UC3, Scalar::all(0.)).unwrap();
+    let mut paint = |x: i32, bgr: Scalar| {
+        rectangle(&mut frame, Rect::new(x, 45, 10, 10), bgr, FILLED, LINE_8, 0).unwrap();
+    };
+    paint(45, Scalar::new(0., 255., 255., 0.));
+    paint(145, Scalar::new(40., 0., 255., 0.));
+
+    assert_eq!(
+        classifier.classify(&frame, &[blob(40)]).unwrap(),
+        ["yellow"]
+    );
+    assert_eq!(classifier.classify(&frame, &[blob(140)]).unwrap(), ["red"]);
+    // Configuration order, whatever the contours order.
+    assert_eq!(
+        classifier.classify(&frame, &[blob(140), blob(40)]).unwrap(),
+        ["yellow", "red"]
+    );
+    // Black area: no tag.
+    assert!(classifier.classify(&frame, &[blob(90)]).unwrap().is_empty());
+}
+
+#[test]
+fn marker_params() {
+    let config: Config = toml::from_str("[markers]\ndictionary = \"apriltag_36h11\"\n").unwrap();
+    let params = config.markers.unwrap();
+    assert_eq!(params.dictionary, MarkerDictionary::AprilTag36h11);
+    assert_eq!(params.margin, 0.5);
+    assert!(toml::from_str::<Config>("[markers]\ndictionary = \"3x3_50\"\n").is_err());
+
+    for margin in [-1., f64::NAN, f64::INFINITY] {
+        assert!(MarkerReader::new(&MarkerParams {
+            margin,
+            ..MarkerParams::default()
+        })
+        .is_err());
+    }
+    // Clean fallback without the contrib module.
+    #[cfg(not(feature = "aruco"))]
+    assert!(MarkerReader::new(&MarkerParams::default()).is_err());
+}
+
+#[cfg(feature = "aruco")]
+#[test]
+fn marker_decoding() {
+    use opencv::{
+        aruco::{draw_marker, get_predefined_dictionary, PREDEFINED_DICTIONARY_NAME},
+        core::{Scalar, CV_8UC1},
+        imgproc::{cvt_color, COLOR_GRAY2BGR},
+    };
+
+    // Marker 7 (100x100 pixels) at (200, 100) on a white canvas.
+    let dictionary = get_predefined_dictionary(PREDEFINED_DICTIONARY_NAME::DICT_4X4_50).unwrap();
+    let mut marker = Mat::default();
+    draw_marker(&dictionary, 7, 100, &mut marker, 1).unwrap();
+    let canvas = Mat::new_rows_cols_with_default(400, 600, CV_8UC1, Scalar::all(255.)).unwrap();
+    marker
+        .copy_to(&mut Mat::roi(&canvas, Rect::new(200, 100, 100, 100)).unwrap())
+        .unwrap();
+    let mut frame = Mat::default();
+    cvt_color(&canvas, &mut frame, COLOR_GRAY2BGR, 0).unwrap();
+
+    // Contour smaller than the marker: the margin takes in the whole marker.
+    let reader = MarkerReader::new(&MarkerParams::default()).unwrap();
+    let blob = Blob {
+        area: 100.,
+        rect: Rect::new(210, 110, 80, 80),
+    };
+    let markers = reader.read(&frame, &[blob]).unwrap();
+    assert_eq!(markers.len(), 1);
+    assert_eq!(markers[0].id, 7);
+    assert!((markers[0].x - 250.).abs() < 1. && (markers[0].y - 150.).abs() < 1.);
+}
+
+#[test]
+fn classifier_params() {
+    let labels = std::env::temp_dir().join("bombuscv-labels.txt");
+    fs::write(&labels, "bee\nwasp\nfly\n").unwrap();
+    let config = |params: &str| -> Config {
+        toml::from_str(&format!(
+            "[classifier]\nmodel = \"model.onnx\"\nlabels = {labels:?}\n{params}"
+        ))
+        .unwrap()
+    };
+
+    let params = config("allow = [\"bee\"]\n").classifier.unwrap();
+    assert_eq!((params.width, params.height), (224, 224));
+    assert_eq!(params.confidence, 0.5);
+    assert!(params.validate().is_ok());
+    assert!(config("allow = []\n")
+        .classifier
+        .unwrap()
+        .validate()
+        .is_err());
+    assert!(config("allow = [\"bee\"]\nconfidence = 1.5\n")
+        .classifier
+        .unwrap()
+        .validate()
+        .is_err());
+    // Allowed labels must be in the labels file (checked before loading the model).
+    assert!(Classifier::new(config("allow = [\"ant\"]\n").classifier.unwrap()).is_err());
+}
+
+#[test]
+fn batch_inputs() {
+    let dir = std::env::temp_dir().join("bombuscv-batch-test");
+    fs::create_dir_all(&dir).unwrap();
+    for file in [
+        "b.mp4",
+        "a.MKV",
+        "notes.txt",
+        "a.MKV_motion.mkv",
+        "a.MKV_motion_0001.debug.mkv",
+        "bee_motion_study.mkv",
+    ] {
+        fs::write(dir.join(file), b"").unwrap();
+    }
+
+    // Directory: video files only (batch outputs excluded), sorted.
+    assert!(input::is_batch(&dir));
+    assert_eq!(
+        input::resolve(&dir).unwrap(),
+        vec![
+            dir.join("a.MKV"),
+            dir.join("b.mp4"),
+            dir.join("bee_motion_study.mkv")
+        ]
+    );
+    // Glob pattern: same filter as directories.
+    assert_eq!(
+        input::resolve(&dir.join("*.mp4")).unwrap(),
+        vec![dir.join("b.mp4")]
+    );
+    assert_eq!(
+        input::resolve(&dir.join("*")).unwrap(),
+        input::resolve(&dir).unwrap()
+    );
+    assert!(input::resolve(&dir.join("*.avi")).is_err());
+
+    assert_eq!(
+        input::batch_format(Path::new("/in/100%_bee.mkv")),
+        "100%%_bee.mkv_motion"
+    );
+    // Inputs differing by extension only.
+    assert_ne!(
+        input::batch_format(Path::new("a.mp4")),
+        input::batch_format(Path::new("a.avi"))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn probe_output() {
+    let devices = [probe::Device {
+        index: 0,
+        modes: vec![probe::Mode {
+            width: 640,
+            height: 480,
+            fps: 30.,
+        }],
+    }];
+
+    assert_eq!(
+        probe::json(&devices),
+        "[{\"device\":\"/dev/video0\",\"index\":0,\"modes\":[{\"width\":640,\"height\":480,\"fps\":30}]}]"
+    );
+    assert_eq!(probe::table(&devices).lines().count(), 2);
+    assert_eq!(probe::json(&[]), "[]");
+    // Non-finite framerate: valid JSON anyway.
+    let devices = [probe::Device {
+        index: 2,
+        modes: vec![probe::Mode {
+            width: 320,
+            height: 240,
+            fps: f64::NAN,
+        }],
+    }];
+    assert_eq!(
+        probe::json(&devices),
+        "[{\"device\":\"/dev/video2\",\"index\":2,\"modes\":[{\"width\":320,\"height\":240,\"fps\":null}]}]"
+    );
+}
+
+/// Serve `video` over HTTP (single connection) from a local TCP listener, returning its URL.
+fn serve_video(video: &Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/{}",
+        listener.local_addr().unwrap(),
+        video.file_name().unwrap().to_str().unwrap()
+    );
+    let data = fs::read(video).unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            // Read (and ignore) the request headers.
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: video/x-matroska\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n",
+                data.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&data);
+        }
+    });
+
+    url
+}
+
+#[test]
+#[ignore = "requires ~/test.mkv and OpenCV built with FFmpeg"]
+fn url_stream() {
+    let home = BaseDirs::new().unwrap().home_dir().to_path_buf();
+    let url = serve_video(&home.join("test.mkv"));
+
+    let mut grabber = Grabber::from_url(&url, Duration::from_secs(5), 30.).unwrap();
+    assert!(grabber.get_fps() > 0.);
+    for _ in 0..10 {
+        let frame = grabber.grab().unwrap();
+        assert!(frame.frame.rows() > 0);
+    }
+}
+
+#[test]
+#[ignore = "requires OpenCV built with FFmpeg"]
+fn url_stream_timeout() {
+    // Listener accepting connections but never responding.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/stream.mjpg", listener.local_addr().unwrap());
+
+    let start = Instant::now();
+    assert!(Grabber::from_url(&url, Duration::from_secs(1), 30.).is_err());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+#[ignore = "requires OpenCV built with GStreamer"]
+fn gstreamer_pipelines() {
+    assert!(gstreamer_available());
+
+    let mut grabber = Grabber::from_pipeline(
+        "videotestsrc num-buffers=30 ! video/x-raw,width=320,height=240,framerate=30/1 ! \
+         videoconvert ! appsink",
+    )
+    .unwrap();
+    assert_eq!(grabber.get_size(), Size::new(320, 240));
+
+    let path = std::env::temp_dir().join("bombuscv-gstreamer-test.mkv");
+    let path = path.to_str().unwrap();
+    let mut writer = Writer::from_pipeline(
+        &format!("appsrc ! videoconvert ! x264enc ! matroskamux ! filesink location={path}"),
+        path,
+        30.,
+        Size::new(320, 240),
+        true,
+        2,
+    )
+    .unwrap();
+
+    for _ in 0..30 {
+        writer.write(grabber.grab().unwrap()).unwrap();
+    }
+    assert_eq!(writer.frames(), 30);
+    drop(writer);
+
+    assert!(fs::metadata(path).unwrap().len() > 0);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,137 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::error::ErrorKind;
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Deserialize;
+use std::{fs::File, io::Write, path::Path, str::FromStr};
+
+/// Frame timestamp sidecar file formats.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    /// SubRip subtitles: one cue per frame.
+    Srt,
+    /// WebVTT subtitles: one cue per frame.
+    Vtt,
+    /// Comma separated values: one row per frame.
+    Csv,
+}
+
+impl TimestampFormat {
+    /// Return the timestamp sidecar file extension.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+            Self::Csv => "timestamps.csv",
+        }
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!(
+                "invalid timestamp format '{s}' (valid: srt, vtt, csv)"
+            )),
+        }
+    }
+}
+
+/// CSV timestamp sidecar header.
+const CSV_HEADER: &str = "frame,position,datetime";
+
+/// Frame timestamp sidecar writer: maps each frame of the output video (by index and playback
+/// position) to its capture date&time.
+///
+/// # Fields
+/// * file: timestamp sidecar file
+/// * format: timestamp sidecar file format
+/// * fps: output video framerate
+#[derive(Debug)]
+pub struct Timestamps {
+    file: File,
+    format: TimestampFormat,
+    fps: f64,
+}
+
+impl Timestamps {
+    /// Create the timestamp sidecar file at `path` (writing the header, where relevant).
+    pub fn create(path: &Path, format: TimestampFormat, fps: f64) -> Result<Self, ErrorKind> {
+        let mut file = File::create(path).map_err(|_| ErrorKind::InvalidTimestamps)?;
+        let header = match format {
+            TimestampFormat::Srt => None,
+            TimestampFormat::Vtt => Some("WEBVTT"),
+            TimestampFormat::Csv => Some(CSV_HEADER),
+        };
+        if let Some(header) = header {
+            writeln!(file, "{header}").map_err(|_| ErrorKind::InvalidTimestamps)?;
+        }
+
+        Ok(Self { file, format, fps })
+    }
+
+    /// Append the timestamp of the output video frame at `index`, captured at `datetime`.
+    pub fn write(&mut self, index: u64, datetime: &DateTime<Local>) -> Result<(), ErrorKind> {
+        // Playback position of the frame, in milliseconds.
+        let start = (index as f64 * 1e3 / self.fps).round() as u64;
+        let end = ((index + 1) as f64 * 1e3 / self.fps).round() as u64;
+
+        let entry = match self.format {
+            TimestampFormat::Srt => format!(
+                "{}\n{} --> {}\n{}\n\n",
+                index + 1,
+                cue_time(start, ','),
+                cue_time(end, ','),
+                datetime.format("%Y-%m-%d %H:%M:%S%.3f"),
+            ),
+            TimestampFormat::Vtt => format!(
+                "\n{} --> {}\n{}\n",
+                cue_time(start, '.'),
+                cue_time(end, '.'),
+                datetime.format("%Y-%m-%d %H:%M:%S%.3f"),
+            ),
+            TimestampFormat::Csv => format!(
+                "{},{:.3},{}\n",
+                index,
+                start as f64 / 1e3,
+                datetime.to_rfc3339_opts(SecondsFormat::Millis, false),
+            ),
+        };
+
+        self.file
+            .write_all(entry.as_bytes())
+            .map_err(|_| ErrorKind::TimestampsWriteErr)
+    }
+}
+
+/// Return `ms` milliseconds as subtitle cue time (`HH:MM:SS<separator>mmm`).
+pub fn cue_time(ms: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
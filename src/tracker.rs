@@ -0,0 +1,640 @@
+// bombuscv: OpenCV based motion detection/recording software built for research on bumblebees.
+// Copyright (C) 2022 Marco Radocchia
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see https://www.gnu.org/licenses/.
+
+use crate::{
+    error::ErrorKind,
+    eventlog::{json_string, LogFormat},
+    sort::{hungarian, KalmanBox},
+    Blob, Detection,
+};
+use chrono::{DateTime, Local, SecondsFormat, Timelike};
+use opencv::core::Rect;
+use serde::Deserialize;
+use std::{cmp::Ordering, collections::BTreeMap, fs::File, io::Write, path::Path};
+
+/// Default value for the maximum centroid distance (pixels) to match a contour to a track.
+fn default_max_distance() -> f64 {
+    50.
+}
+
+/// Default value for the minimum bounding rectangles IoU to match a contour to a track.
+fn default_min_iou() -> f64 {
+    0.1
+}
+
+/// Default value for the number of frames a track survives without matching contours.
+fn default_max_missed() -> u32 {
+    5
+}
+
+/// Default value for the number of matched frames before a track is confirmed.
+fn default_min_hits() -> u32 {
+    1
+}
+
+/// Contour to track matching method.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackerMethod {
+    /// Greedy matching to the last track positions, by bounding rectangles IoU, then centroid
+    /// distance.
+    Centroid,
+    /// SORT: Kalman filter prediction of the track boxes, then optimal (Hungarian) matching.
+    Sort,
+}
+
+impl Default for TrackerMethod {
+    fn default() -> Self {
+        Self::Centroid
+    }
+}
+
+/// Counting boundary in original frame coordinates.
+///
+/// Deserialized from a single-key table, i.e. `{ line = [[x, y], [x, y]] }` or
+/// `{ polygon = [[x, y], [x, y], [x, y]] }`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// Line segment as `[[x, y], [x, y]]`: crossing it from left to right (looking from the first
+    /// to the second point in the frame) counts as in, from right to left as out.
+    Line([[i32; 2]; 2]),
+    /// Polygon as list of `[x, y]` vertices (i.e. the nest entrance): entering it counts as in,
+    /// leaving it as out.
+    Polygon(Vec<[i32; 2]>),
+}
+
+impl Boundary {
+    /// Check boundary consistency.
+    fn validate(&self) -> Result<(), ErrorKind> {
+        match self {
+            Self::Line([a, b]) if a == b => Err(ErrorKind::InvalidTrackerParams(String::from(
+                "boundary line points must be distinct",
+            ))),
+            Self::Polygon(vertices) if vertices.len() < 3 => {
+                Err(ErrorKind::InvalidTrackerParams(format!(
+                    "boundary polygon must have at least 3 vertices, found {}",
+                    vertices.len()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Return the direction a track moving from `from` to `to` crosses the boundary, if it does.
+    pub fn crossing(&self, from: (f64, f64), to: (f64, f64)) -> Option<Direction> {
+        match self {
+            Self::Line([a, b]) => {
+                let (a, b) = (point(*a), point(*b));
+                // Positive cross product means right of the line (the frame y axis points down):
+                // points on the line count as right of it.
+                let (from_right, to_right) = (cross(a, b, from) >= 0., cross(a, b, to) >= 0.);
+                // The track move must go across the line segment.
+                if from_right == to_right || cross(from, to, a) * cross(from, to, b) > 0. {
+                    return None;
+                }
+                match to_right {
+                    true => Some(Direction::In),
+                    false => Some(Direction::Out),
+                }
+            }
+            Self::Polygon(vertices) => match (contains(vertices, from), contains(vertices, to)) {
+                (false, true) => Some(Direction::In),
+                (true, false) => Some(Direction::Out),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Convert `[x, y]` vertex to point.
+fn point([x, y]: [i32; 2]) -> (f64, f64) {
+    (f64::from(x), f64::from(y))
+}
+
+/// Return the cross product of `a -> b` and `a -> c` vectors: its sign tells which side of the
+/// `a -> b` line `c` lies on.
+fn cross(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Return true if `polygon` contains point `p` (ray casting).
+fn contains(polygon: &[[i32; 2]], p: (f64, f64)) -> bool {
+    let mut inside = false;
+    // Edges from the previous vertex `b` to the current one `a`.
+    let mut b = point(polygon[polygon.len() - 1]);
+    for vertex in polygon {
+        let a = point(*vertex);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < (b.0 - a.0) * (p.1 - a.1) / (b.1 - a.1) + a.0 {
+            inside = !inside;
+        }
+        b = a;
+    }
+
+    inside
+}
+
+/// Return the centroid of `rect`.
+fn centroid(rect: Rect) -> (f64, f64) {
+    (
+        f64::from(rect.x) + f64::from(rect.width) / 2.,
+        f64::from(rect.y) + f64::from(rect.height) / 2.,
+    )
+}
+
+/// Return the intersection over union of `a` & `b` rectangles.
+fn iou(a: Rect, b: Rect) -> f64 {
+    let intersection = (a & b).area();
+    let union = a.area() + b.area() - intersection;
+    match union > 0 {
+        true => f64::from(intersection) / f64::from(union),
+        false => 0.,
+    }
+}
+
+/// Boundary crossing direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// Boundary crossing by a track.
+///
+/// # Fields
+/// * track: track ID
+/// * direction: crossing direction
+/// * datetime: date&time of the frame the crossing was detected in
+#[derive(Debug, Clone, Copy)]
+pub struct Crossing {
+    pub track: u64,
+    pub direction: Direction,
+    pub datetime: DateTime<Local>,
+}
+
+/// Tracking & counting parameters.
+///
+/// # Fields
+/// * method: contour to track matching method
+/// * boundary: counting boundary (original frame coordinates)
+/// * trajectories: per-track trajectories file format
+/// * max_distance: maximum centroid distance (pixels) to match a contour to a track
+/// * min_iou: minimum bounding rectangles IoU to match a contour to a track
+/// * max_missed: frames a track survives without matching contours
+/// * min_hits: matched frames before a track is confirmed (counted & logged)
+#[derive(Deserialize, Debug, Clone)]
+pub struct TrackerParams {
+    #[serde(default)]
+    pub method: TrackerMethod,
+    #[serde(default)]
+    pub boundary: Option<Boundary>,
+    #[serde(default)]
+    pub trajectories: Option<LogFormat>,
+    #[serde(default = "default_max_distance")]
+    pub max_distance: f64,
+    #[serde(default = "default_min_iou")]
+    pub min_iou: f64,
+    #[serde(default = "default_max_missed")]
+    pub max_missed: u32,
+    #[serde(default = "default_min_hits")]
+    pub min_hits: u32,
+}
+
+impl Default for TrackerParams {
+    fn default() -> Self {
+        Self {
+            method: TrackerMethod::default(),
+            boundary: None,
+            trajectories: None,
+            max_distance: default_max_distance(),
+            min_iou: default_min_iou(),
+            max_missed: default_max_missed(),
+            min_hits: default_min_hits(),
+        }
+    }
+}
+
+impl TrackerParams {
+    /// Return whether tracking is needed: counting boundary or trajectories set.
+    pub fn enabled(&self) -> bool {
+        self.boundary.is_some() || self.trajectories.is_some()
+    }
+
+    /// Check tracking parameters consistency.
+    pub fn validate(&self) -> Result<(), ErrorKind> {
+        if let Some(boundary) = &self.boundary {
+            boundary.validate()?;
+        }
+
+        if !self.max_distance.is_finite() || self.max_distance < 0. {
+            return Err(ErrorKind::InvalidTrackerParams(format!(
+                "max_distance must be finite and non-negative, found {}",
+                self.max_distance
+            )));
+        }
+
+        // IoU is never negative: a null min_iou would match any contour to any track.
+        if !(self.min_iou > 0. && self.min_iou <= 1.) {
+            return Err(ErrorKind::InvalidTrackerParams(format!(
+                "min_iou must be in (0, 1], found {}",
+                self.min_iou
+            )));
+        }
+
+        if self.min_hits == 0 {
+            return Err(ErrorKind::InvalidTrackerParams(String::from(
+                "min_hits must be at least 1",
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracked object.
+///
+/// # Fields
+/// * id: track ID
+/// * rect: last bounding rectangle (Kalman filter estimate with SORT method)
+/// * missed: consecutive frames without matching contours
+/// * hits: frames with matching contours
+/// * kalman: bounding box Kalman filter (SORT method)
+#[derive(Debug, Clone)]
+struct Track {
+    id: u64,
+    rect: Rect,
+    missed: u32,
+    hits: u32,
+    kalman: Option<KalmanBox>,
+}
+
+/// Tracked object position in a frame.
+///
+/// # Fields
+/// * track: track ID
+/// * rect: track bounding rectangle in original frame coordinates
+/// * area: matched contour area, as fraction of the frame area
+#[derive(Debug, Clone, Copy)]
+pub struct TrackPoint {
+    pub track: u64,
+    pub rect: Rect,
+    pub area: f64,
+}
+
+/// Contour tracker: matches the motion contours frame to frame, giving them track IDs, and
+/// counts the tracks crossing the boundary.
+///
+/// # Fields
+/// * params: tracking parameters
+/// * tracks: live tracks
+/// * next_id: ID of the next track
+/// * counts: per-hour boundary crossing counts
+/// * trajectories: per-track trajectories file
+#[derive(Debug)]
+pub struct Tracker {
+    params: TrackerParams,
+    tracks: Vec<Track>,
+    next_id: u64,
+    counts: Counts,
+    trajectories: Option<Trajectories>,
+}
+
+impl Tracker {
+    /// Create an instance of the Tracker, return Err if `params` are not valid.
+    pub fn new(params: TrackerParams) -> Result<Self, ErrorKind> {
+        params.validate()?;
+
+        Ok(Self {
+            params,
+            tracks: Vec::new(),
+            next_id: 1,
+            counts: Counts::default(),
+            trajectories: None,
+        })
+    }
+
+    /// Match the `detection` motion contours to the tracks and record the confirmed track positions
+    /// & boundary crossings in the detection.
+    pub fn update(&mut self, detection: &mut Detection) {
+        // Expected track boxes in the current frame.
+        let predicted: Vec<Rect> = self
+            .tracks
+            .iter_mut()
+            .map(|track| match track.kalman.as_mut() {
+                Some(kalman) => kalman.predict(),
+                None => track.rect,
+            })
+            .collect();
+
+        let pairs = match self.params.method {
+            TrackerMethod::Centroid => self.match_greedy(&predicted, &detection.blobs),
+            TrackerMethod::Sort => self.match_optimal(&predicted, &detection.blobs),
+        };
+
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        let mut matched_blobs = vec![false; detection.blobs.len()];
+        for (t, b) in pairs {
+            matched_tracks[t] = true;
+            matched_blobs[b] = true;
+
+            let track = &mut self.tracks[t];
+            let blob = detection.blobs[b];
+            let rect = match track.kalman.as_mut() {
+                Some(kalman) => kalman.update(blob.rect),
+                None => blob.rect,
+            };
+            track.missed = 0;
+            track.hits += 1;
+
+            if track.hits >= self.params.min_hits {
+                if let Some(boundary) = &self.params.boundary {
+                    if let Some(direction) = boundary.crossing(centroid(track.rect), centroid(rect))
+                    {
+                        let crossing = Crossing {
+                            track: track.id,
+                            direction,
+                            datetime: detection.frame.datetime,
+                        };
+                        self.counts.push(&crossing);
+                        detection.crossings.push(crossing);
+                    }
+                }
+                detection.tracks.push(TrackPoint {
+                    track: track.id,
+                    rect,
+                    area: blob.area / detection.frame_area,
+                });
+            }
+            track.rect = rect;
+        }
+
+        // Age unmatched tracks, dropping the lost ones.
+        for (track, matched) in self.tracks.iter_mut().zip(&matched_tracks) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        let max_missed = self.params.max_missed;
+        self.tracks.retain(|track| track.missed <= max_missed);
+
+        // Unmatched contours start new tracks.
+        for (blob, _) in detection
+            .blobs
+            .iter()
+            .zip(matched_blobs)
+            .filter(|(_, m)| !m)
+        {
+            let track = Track {
+                id: self.next_id,
+                rect: blob.rect,
+                missed: 0,
+                hits: 1,
+                kalman: match self.params.method {
+                    TrackerMethod::Centroid => None,
+                    TrackerMethod::Sort => Some(KalmanBox::new(blob.rect)),
+                },
+            };
+            if track.hits >= self.params.min_hits {
+                detection.tracks.push(TrackPoint {
+                    track: track.id,
+                    rect: track.rect,
+                    area: blob.area / detection.frame_area,
+                });
+            }
+            self.tracks.push(track);
+            self.next_id += 1;
+        }
+    }
+
+    /// Return whether the `predicted` track box & the `blob` contour box are close enough to
+    /// match, along with their IoU & centroid distance.
+    fn gate(&self, predicted: Rect, blob: Rect) -> Option<(f64, f64)> {
+        let overlap = iou(predicted, blob);
+        let (from, to) = (centroid(predicted), centroid(blob));
+        let distance = (to.0 - from.0).hypot(to.1 - from.1);
+        match overlap >= self.params.min_iou || distance <= self.params.max_distance {
+            true => Some((overlap, distance)),
+            false => None,
+        }
+    }
+
+    /// Match the `predicted` track boxes to the `blobs` greedily, best matches (highest IoU, then
+    /// shortest centroid distance) first. Return the matched (track, contour) index pairs.
+    fn match_greedy(&self, predicted: &[Rect], blobs: &[Blob]) -> Vec<(usize, usize)> {
+        // Candidate (track, contour) pairs, best matches first.
+        let mut pairs = Vec::new();
+        for (t, rect) in predicted.iter().enumerate() {
+            for (b, blob) in blobs.iter().enumerate() {
+                if let Some((overlap, distance)) = self.gate(*rect, blob.rect) {
+                    pairs.push((t, b, overlap, distance));
+                }
+            }
+        }
+        pairs.sort_by(|x, y| {
+            (y.2, x.3)
+                .partial_cmp(&(x.2, y.3))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut matched_tracks = vec![false; predicted.len()];
+        let mut matched_blobs = vec![false; blobs.len()];
+        let mut matches = Vec::new();
+        for (t, b, _, _) in pairs {
+            if matched_tracks[t] || matched_blobs[b] {
+                continue;
+            }
+            matched_tracks[t] = true;
+            matched_blobs[b] = true;
+            matches.push((t, b));
+        }
+
+        matches
+    }
+
+    /// Match the `predicted` track boxes to the `blobs` minimizing the total cost, i.e. 1 - IoU
+    /// plus the centroid distance as fraction of `max_distance`. Return the matched (track,
+    /// contour) index pairs.
+    fn match_optimal(&self, predicted: &[Rect], blobs: &[Blob]) -> Vec<(usize, usize)> {
+        // Pairs out of the gate get a prohibitive cost, so they're only assigned if there's no
+        // alternative: those assignments are then discarded.
+        const NO_MATCH: f64 = 1e6;
+
+        let max_distance = self.params.max_distance.max(1.);
+        let cost: Vec<Vec<f64>> = predicted
+            .iter()
+            .map(|rect| {
+                blobs
+                    .iter()
+                    .map(|blob| match self.gate(*rect, blob.rect) {
+                        Some((overlap, distance)) => 1. - overlap + distance / max_distance,
+                        None => NO_MATCH,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        hungarian(&cost)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(t, b)| b.map(|b| (t, b)))
+            .filter(|&(t, b)| cost[t][b] < NO_MATCH)
+            .collect()
+    }
+
+    /// Write the trajectories of every processed frame to `trajectories`.
+    pub fn set_trajectories(&mut self, trajectories: Trajectories) {
+        self.trajectories = Some(trajectories);
+    }
+
+    /// Append the `detection` track positions to the trajectories file (if any).
+    pub fn log(&mut self, detection: &Detection) -> Result<(), ErrorKind> {
+        match self.trajectories.as_mut() {
+            Some(trajectories) => trajectories.write(detection),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the per-hour boundary crossing counts.
+    pub fn counts(&self) -> &Counts {
+        &self.counts
+    }
+}
+
+/// Per-hour boundary crossing counts.
+///
+/// # Fields
+/// * hours: in & out counts, by hour start date&time
+#[derive(Debug, Clone, Default)]
+pub struct Counts {
+    hours: BTreeMap<DateTime<Local>, (u32, u32)>,
+}
+
+impl Counts {
+    /// Count `crossing` in its hour.
+    pub fn push(&mut self, crossing: &Crossing) {
+        let hour = crossing
+            .datetime
+            .with_minute(0)
+            .and_then(|datetime| datetime.with_second(0))
+            .and_then(|datetime| datetime.with_nanosecond(0))
+            .unwrap_or(crossing.datetime);
+
+        let (count_in, count_out) = self.hours.entry(hour).or_default();
+        match crossing.direction {
+            Direction::In => *count_in += 1,
+            Direction::Out => *count_out += 1,
+        }
+    }
+
+    /// Return the in & out counts, by hour start date&time (oldest first).
+    pub fn hours(&self) -> impl Iterator<Item = (DateTime<Local>, u32, u32)> + '_ {
+        self.hours
+            .iter()
+            .map(|(hour, (count_in, count_out))| (*hour, *count_in, *count_out))
+    }
+
+    /// Return the total in & out counts.
+    pub fn total(&self) -> (u32, u32) {
+        self.hours.values().fold((0, 0), |total, counts| {
+            (total.0 + counts.0, total.1 + counts.1)
+        })
+    }
+
+    /// Write the per-hour counts to `path`, one record per hour.
+    pub fn write(&self, path: &Path, format: LogFormat) -> Result<(), ErrorKind> {
+        let mut content = match format {
+            LogFormat::Jsonl => String::new(),
+            LogFormat::Csv => String::from("hour,in,out\n"),
+        };
+        for (hour, count_in, count_out) in self.hours() {
+            let hour = hour.to_rfc3339_opts(SecondsFormat::Secs, false);
+            content.push_str(&match format {
+                LogFormat::Jsonl => format!(
+                    "{{\"hour\":{},\"in\":{count_in},\"out\":{count_out}}}\n",
+                    json_string(&hour)
+                ),
+                LogFormat::Csv => format!("{hour},{count_in},{count_out}\n"),
+            });
+        }
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|_| ErrorKind::CountsWriteErr)
+    }
+}
+
+/// Trajectories file CSV header.
+const TRAJECTORIES_CSV_HEADER: &str = "track,datetime,frame,x,y,left,top,width,height,area";
+
+/// Per-track trajectories file: one record per confirmed track per frame, with the track
+/// centroid, bounding rectangle and area.
+///
+/// # Fields
+/// * file: trajectories file
+/// * format: trajectories file format
+#[derive(Debug)]
+pub struct Trajectories {
+    file: File,
+    format: LogFormat,
+}
+
+impl Trajectories {
+    /// Create the trajectories file at `path` (writing the header, where relevant).
+    pub fn create(path: &Path, format: LogFormat) -> Result<Self, ErrorKind> {
+        let mut file = File::create(path).map_err(|_| ErrorKind::InvalidTrajectories)?;
+        if format == LogFormat::Csv {
+            writeln!(file, "{TRAJECTORIES_CSV_HEADER}")
+                .map_err(|_| ErrorKind::InvalidTrajectories)?;
+        }
+
+        Ok(Self { file, format })
+    }
+
+    /// Append the `detection` track positions to the trajectories file.
+    pub fn write(&mut self, detection: &Detection) -> Result<(), ErrorKind> {
+        let datetime = detection
+            .frame
+            .datetime
+            .to_rfc3339_opts(SecondsFormat::Millis, false);
+
+        let mut content = String::new();
+        for point in &detection.tracks {
+            let (x, y) = centroid(point.rect);
+            let Rect {
+                x: left,
+                y: top,
+                width,
+                height,
+            } = point.rect;
+            let (track, frame, area) = (point.track, detection.index, point.area);
+            content.push_str(&match self.format {
+                LogFormat::Jsonl => format!(
+                    "{{\"track\":{track},\"datetime\":{},\"frame\":{frame},\"x\":{x},\"y\":{y},\
+                     \"left\":{left},\"top\":{top},\"width\":{width},\"height\":{height},\
+                     \"area\":{area:.6}}}\n",
+                    json_string(&datetime)
+                ),
+                LogFormat::Csv => format!(
+                    "{track},{datetime},{frame},{x},{y},{left},{top},{width},{height},{area:.6}\n"
+                ),
+            });
+        }
+
+        self.file
+            .write_all(content.as_bytes())
+            .map_err(|_| ErrorKind::TrajectoriesWriteErr)
+    }
+}